@@ -0,0 +1,171 @@
+use crate::{
+    error::{CliError, OrExit, CHECK_FAILED_EXIT_CODE},
+    metrics,
+    status::SharedStatus,
+};
+use clap::{App, Arg, ArgMatches};
+use hyper::{
+    server::{conn::AddrIncoming, Builder},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use std::{
+    convert::Infallible,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+pub fn add_args<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a> {
+    app.arg(
+        Arg::with_name("admin_listen")
+            .long("admin-listen")
+            .value_name("ADDR:PORT")
+            .help("Serve /healthz, /readyz, /status and /metrics on this address, e.g. 127.0.0.1:3030")
+            .env("EXG_ADMIN_LISTEN")
+            .required(false)
+            .takes_value(true),
+    )
+}
+
+fn respond(status: StatusCode, body: String) -> Response<Body> {
+    respond_with_content_type(status, "application/json", body)
+}
+
+fn respond_with_content_type(
+    status: StatusCode,
+    content_type: &str,
+    body: String,
+) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", content_type)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn handle(req: Request<Body>, status: &SharedStatus) -> Response<Body> {
+    if req.method() != Method::GET {
+        return respond(StatusCode::METHOD_NOT_ALLOWED, "{}".to_string());
+    }
+
+    let snapshot = status.snapshot();
+
+    let check = |is_ok: bool| {
+        if is_ok {
+            respond(StatusCode::OK, r#"{"status":"ok"}"#.to_string())
+        } else {
+            respond(
+                StatusCode::SERVICE_UNAVAILABLE,
+                r#"{"status":"unavailable"}"#.to_string(),
+            )
+        }
+    };
+
+    match req.uri().path() {
+        "/healthz" => check(snapshot.is_healthy()),
+        "/readyz" => check(snapshot.is_ready()),
+        "/status" => respond(
+            StatusCode::OK,
+            serde_json::to_string_pretty(&snapshot).unwrap(),
+        ),
+        "/metrics" => respond_with_content_type(
+            StatusCode::OK,
+            "text/plain; version=0.0.4",
+            metrics::render(status),
+        ),
+        _ => respond(StatusCode::NOT_FOUND, "{}".to_string()),
+    }
+}
+
+/// Bind the admin endpoint, so that spawn fails if the address is not
+/// available
+pub fn bind(addr: SocketAddr) -> Result<Builder<AddrIncoming>, CliError> {
+    hyper::Server::try_bind(&addr)
+        .map_err(|e| CliError::os(format!("could not start admin endpoint on {}: {}", addr, e)))
+}
+
+/// Serve admin endpoint until the app stops
+pub async fn serve(builder: Builder<AddrIncoming>, status: SharedStatus) {
+    let make_svc = make_service_fn(move |_conn| {
+        let status = status.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let resp = handle(req, &status);
+                async move { Ok::<_, Infallible>(resp) }
+            }))
+        }
+    });
+
+    let server = builder.serve(make_svc);
+    info!(
+        "admin endpoint is listening on http://{}",
+        server.local_addr()
+    );
+    if let Err(e) = server.await {
+        error!("admin endpoint stopped with error: {}", e);
+    }
+}
+
+pub fn healthcheck_app() -> App<'static, 'static> {
+    App::new("healthcheck")
+        .about("Query admin endpoint of the running instance, e.g. for Docker HEALTHCHECK")
+        .arg(
+            Arg::with_name("admin_listen")
+                .long("admin-listen")
+                .value_name("ADDR:PORT")
+                .help("Address of the admin endpoint")
+                .env("EXG_ADMIN_LISTEN")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .value_name("CHECK")
+                .help("Which check to query")
+                .possible_values(&["healthz", "readyz"])
+                .default_value("readyz")
+                .takes_value(true),
+        )
+}
+
+fn query(addr: SocketAddr, path: &str) -> std::io::Result<u16> {
+    let timeout = Duration::from_secs(5);
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, addr
+    )?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+
+    response
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "bad response"))
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    let addr: SocketAddr =
+        crate::error::parse_required_arg(args, "admin_listen", "--admin-listen (EXG_ADMIN_LISTEN)")
+            .or_exit();
+    let check = args.value_of("check").unwrap();
+
+    let status_code = query(addr, &format!("/{}", check))
+        .map_err(|e| CliError::network(format!("could not query {}: {}", addr, e)))
+        .or_exit();
+
+    if status_code == 200 {
+        println!("{}: ok", check);
+        std::process::exit(0);
+    } else {
+        println!("{}: failed with status {}", check, status_code);
+        std::process::exit(CHECK_FAILED_EXIT_CODE);
+    }
+}
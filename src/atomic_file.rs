@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Context};
+use std::{fs, io::Write, path::Path};
+
+/// Write the file atomically: the content is written to a temporary file
+/// next to it, which then replaces the file, so that readers never see a
+/// partially written file.
+///
+/// On unix the file gets `mode` if it is set. Otherwise permissions of the
+/// existing file are kept, and new files are created with the default ones.
+pub fn write(path: &Path, content: &[u8], mode: Option<u32>) -> anyhow::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("bad path {}", path.display()))?;
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+
+    let permissions = match mode {
+        Some(_) => None,
+        None => fs::metadata(path).ok().map(|m| m.permissions()),
+    };
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        if let Some(mode) = mode {
+            options.mode(mode);
+        }
+    }
+
+    let write = || -> anyhow::Result<()> {
+        // the temporary file may be left from a failed write with other
+        // permissions, `mode` only applies to new files
+        if tmp_path.exists() {
+            fs::remove_file(&tmp_path)?;
+        }
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(content)?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    };
+
+    write().or_else(|e| {
+        fs::remove_file(&tmp_path).ok();
+        Err(e).with_context(|| format!("could not write {}", path.display()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "exogress-atomic-file-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn replaces_content() {
+        let path = dir("replace").join("Exofile.yml");
+        fs::write(&path, "old content, which is longer").unwrap();
+
+        write(&path, b"new", None).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!path.with_file_name(".Exofile.yml.tmp").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let dir = dir("permissions");
+
+        let existing = dir.join("Exofile.yml");
+        fs::write(&existing, "").unwrap();
+        fs::set_permissions(&existing, fs::Permissions::from_mode(0o640)).unwrap();
+        write(&existing, b"content", None).unwrap();
+        assert_eq!(mode(&existing), 0o640);
+
+        let secret = dir.join("credentials");
+        fs::write(&secret, "").unwrap();
+        fs::set_permissions(&secret, fs::Permissions::from_mode(0o644)).unwrap();
+        write(&secret, b"content", Some(0o600)).unwrap();
+        assert_eq!(mode(&secret), 0o600);
+    }
+}
@@ -0,0 +1,280 @@
+use anyhow::anyhow;
+use serde_json::json;
+use std::{fmt, str::FromStr};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildLogFormat {
+    /// Copy output as is
+    Raw,
+    /// Line-buffered output with optional prefix and timestamp
+    Text,
+    /// Each line is wrapped into JSON event
+    Json,
+}
+
+impl ChildLogFormat {
+    pub const VARIANTS: &'static [&'static str] = &["raw", "text", "json"];
+}
+
+impl FromStr for ChildLogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(ChildLogFormat::Raw),
+            "text" => Ok(ChildLogFormat::Text),
+            "json" => Ok(ChildLogFormat::Json),
+            _ => Err(anyhow!("unknown child log format `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl fmt::Display for OutputStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputStream::Stdout => write!(f, "stdout"),
+            OutputStream::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChildLogConfig {
+    pub format: ChildLogFormat,
+    pub prefix: Option<String>,
+    pub timestamps: bool,
+}
+
+impl ChildLogConfig {
+    fn format_line(
+        &self,
+        line: &str,
+        stream: OutputStream,
+        pid: Option<u32>,
+        process: Option<&str>,
+    ) -> String {
+        let now = chrono::Utc::now();
+
+        match self.format {
+            ChildLogFormat::Json => {
+                let mut event = json!({
+                    "timestamp": now.to_rfc3339(),
+                    "stream": stream.to_string(),
+                    "pid": pid,
+                    "message": line,
+                });
+                if let Some(process) = process {
+                    event["process"] = process.trim().into();
+                }
+                if let Some(prefix) = &self.prefix {
+                    event["prefix"] = prefix.as_str().into();
+                }
+                format!("{}\n", event)
+            }
+            ChildLogFormat::Text | ChildLogFormat::Raw => {
+                let mut formatted = String::new();
+                if self.timestamps {
+                    formatted.push_str(&now.to_rfc3339());
+                    formatted.push(' ');
+                }
+                if let Some(prefix) = process.or_else(|| self.prefix.as_deref()) {
+                    formatted.push_str(prefix);
+                    formatted.push_str(" | ");
+                }
+                formatted.push_str(line);
+                formatted.push('\n');
+                formatted
+            }
+        }
+    }
+
+    /// Forward child output to the writer according to the configured format.
+    /// `process` is the name of the process in the Procfile mode.
+    pub async fn forward<R, W>(
+        self,
+        mut reader: R,
+        mut writer: W,
+        stream: OutputStream,
+        pid: Option<u32>,
+        process: Option<String>,
+    ) where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let is_raw = self.format == ChildLogFormat::Raw
+            && process.is_none()
+            && self.prefix.is_none()
+            && !self.timestamps;
+
+        if is_raw {
+            tokio::io::copy(&mut reader, &mut writer).await.ok();
+            return;
+        }
+
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            let formatted = self.format_line(line, stream, pid, process.as_deref());
+
+            if writer.write_all(formatted.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn config(format: ChildLogFormat, prefix: Option<&str>) -> ChildLogConfig {
+        ChildLogConfig {
+            format,
+            prefix: prefix.map(|p| p.to_string()),
+            timestamps: false,
+        }
+    }
+
+    async fn forward(
+        config: ChildLogConfig,
+        input: &[u8],
+        stream: OutputStream,
+        process: Option<&str>,
+    ) -> String {
+        let mut output = vec![];
+        config
+            .forward(
+                input,
+                &mut output,
+                stream,
+                Some(42),
+                process.map(|p| p.to_string()),
+            )
+            .await;
+        String::from_utf8(output).unwrap()
+    }
+
+    fn events(output: &str) -> Vec<Value> {
+        output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn raw_is_copied_as_is() {
+        let output = forward(
+            config(ChildLogFormat::Raw, None),
+            b"partial\r\nline",
+            OutputStream::Stdout,
+            None,
+        )
+        .await;
+
+        assert_eq!(output, "partial\r\nline");
+    }
+
+    #[tokio::test]
+    async fn text_with_prefix() {
+        let output = forward(
+            config(ChildLogFormat::Text, Some("app")),
+            b"first\r\nsecond",
+            OutputStream::Stderr,
+            None,
+        )
+        .await;
+
+        assert_eq!(output, "app | first\napp | second\n");
+    }
+
+    #[tokio::test]
+    async fn text_process_name_replaces_prefix() {
+        let output = forward(
+            config(ChildLogFormat::Text, Some("app")),
+            b"line\n",
+            OutputStream::Stdout,
+            Some("web "),
+        )
+        .await;
+
+        assert_eq!(output, "web  | line\n");
+    }
+
+    #[tokio::test]
+    async fn json_fields() {
+        let output = forward(
+            config(ChildLogFormat::Json, Some("app")),
+            b"out\n",
+            OutputStream::Stdout,
+            Some("web "),
+        )
+        .await;
+        let events = events(&output);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["stream"], "stdout");
+        assert_eq!(events[0]["pid"], 42);
+        assert_eq!(events[0]["message"], "out");
+        assert_eq!(events[0]["process"], "web");
+        assert_eq!(events[0]["prefix"], "app");
+        assert!(events[0]["timestamp"].is_string());
+    }
+
+    #[tokio::test]
+    async fn json_stderr_without_prefix() {
+        let output = forward(
+            config(ChildLogFormat::Json, None),
+            b"err\n",
+            OutputStream::Stderr,
+            None,
+        )
+        .await;
+        let events = events(&output);
+
+        assert_eq!(events[0]["stream"], "stderr");
+        assert!(events[0].get("prefix").is_none());
+        assert!(events[0].get("process").is_none());
+    }
+
+    #[tokio::test]
+    async fn non_utf8_lines_are_replaced() {
+        let input = b"bad \xff byte\nnext\n";
+
+        let text = forward(
+            config(ChildLogFormat::Text, Some("app")),
+            input,
+            OutputStream::Stdout,
+            None,
+        )
+        .await;
+        assert_eq!(text, "app | bad \u{fffd} byte\napp | next\n");
+
+        let json = forward(
+            config(ChildLogFormat::Json, None),
+            input,
+            OutputStream::Stdout,
+            None,
+        )
+        .await;
+        let events = events(&json);
+        assert_eq!(events[0]["message"], "bad \u{fffd} byte");
+        assert_eq!(events[1]["message"], "next");
+    }
+}
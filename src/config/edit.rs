@@ -0,0 +1,434 @@
+use crate::{
+    atomic_file,
+    config::{
+        add_config_arg, bump_revision,
+        format::{self, Document, Entry, Value},
+        print_diff,
+        validate::validate,
+        Severity,
+    },
+    error::{parse_required_arg, CliError, OrExit, CONFIG_ERROR_EXIT_CODE},
+    exofile::Exofile,
+};
+use anyhow::{anyhow, bail, Context};
+use clap::{App, AppSettings, Arg, ArgMatches};
+use std::{fs, path::Path};
+
+const ADD_SUBCOMMAND: &str = "add";
+const REMOVE_SUBCOMMAND: &str = "remove";
+
+const UPSTREAM: &str = "upstream";
+const HANDLER: &str = "handler";
+const MOUNT_POINT: &str = "mount-point";
+
+fn name_arg(help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("name")
+        .value_name("NAME")
+        .help(help)
+        .required(true)
+        .takes_value(true)
+}
+
+fn mount_point_arg() -> Arg<'static, 'static> {
+    Arg::with_name("mount_point")
+        .long("mount-point")
+        .value_name("NAME")
+        .help("Mount point of the handler")
+        .default_value("default")
+        .takes_value(true)
+}
+
+fn profile_arg() -> Arg<'static, 'static> {
+    Arg::with_name("profile")
+        .long("profile")
+        .value_name("PROFILE")
+        .help("Activate only in the profile")
+        .multiple(true)
+        .number_of_values(1)
+        .takes_value(true)
+}
+
+fn dry_run_arg() -> Arg<'static, 'static> {
+    Arg::with_name("dry_run")
+        .long("dry-run")
+        .help("Print the diff without writing the file")
+}
+
+pub fn add_subcommand() -> App<'static, 'static> {
+    App::new(ADD_SUBCOMMAND)
+        .about("Add item to Exofile")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            add_config_arg(App::new(UPSTREAM))
+                .about("Add upstream")
+                .arg(name_arg("Upstream name"))
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .short("p")
+                        .value_name("PORT")
+                        .help("Upstream port")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("host")
+                        .long("host")
+                        .value_name("HOST")
+                        .help("Upstream host [default: 127.0.0.1]")
+                        .takes_value(true),
+                )
+                .arg(profile_arg())
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            add_config_arg(App::new(HANDLER))
+                .about("Add handler to the mount point")
+                .arg(name_arg("Handler name"))
+                .arg(mount_point_arg())
+                .arg(
+                    Arg::with_name("kind")
+                        .long("kind")
+                        .value_name("KIND")
+                        .help("Handler kind, e.g. proxy or static-dir")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("priority")
+                        .long("priority")
+                        .value_name("PRIORITY")
+                        .help("Handler priority, handlers with lower values are invoked first")
+                        .default_value("50")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("upstream")
+                        .long("upstream")
+                        .value_name("UPSTREAM")
+                        .help("Upstream of the proxy handler")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .long("dir")
+                        .value_name("DIR")
+                        .help("Directory of the static-dir handler")
+                        .takes_value(true),
+                )
+                .arg(profile_arg())
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            add_config_arg(App::new(MOUNT_POINT))
+                .about("Add mount point")
+                .arg(name_arg("Mount point name"))
+                .arg(dry_run_arg()),
+        )
+}
+
+pub fn remove_subcommand() -> App<'static, 'static> {
+    App::new(REMOVE_SUBCOMMAND)
+        .about("Remove item from Exofile")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            add_config_arg(App::new(UPSTREAM))
+                .about("Remove upstream")
+                .arg(name_arg("Upstream name"))
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            add_config_arg(App::new(HANDLER))
+                .about("Remove handler from the mount point")
+                .arg(name_arg("Handler name"))
+                .arg(mount_point_arg())
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            add_config_arg(App::new(MOUNT_POINT))
+                .about("Remove mount point with all its handlers")
+                .arg(name_arg("Mount point name"))
+                .arg(dry_run_arg()),
+        )
+}
+
+/// Quote the string, unless it may be written as is
+fn scalar(s: &str) -> Value {
+    let is_plain = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./".contains(c))
+        && !s.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && !["true", "false", "yes", "no", "on", "off", "null"]
+            .contains(&s.to_lowercase().as_str());
+
+    if is_plain {
+        Value::Scalar(s.to_string())
+    } else {
+        Value::Scalar(format!(
+            "\"{}\"",
+            s.replace('\\', r"\\").replace('"', r#"\""#)
+        ))
+    }
+}
+
+fn profiles(args: &ArgMatches) -> Option<Value> {
+    let profiles = args.values_of("profile")?;
+    let profiles = profiles
+        .map(|p| format!("\"{}\"", p.replace('\\', r"\\").replace('"', r#"\""#)))
+        .collect::<Vec<_>>();
+
+    Some(Value::Scalar(format!("[{}]", profiles.join(", "))))
+}
+
+fn insert(entries: &mut Vec<Entry>, kind: &str, name: &str, value: Value) -> anyhow::Result<()> {
+    if format::position(entries, name).is_some() {
+        bail!("{} `{}` already exists", kind, name);
+    }
+    entries.push(Entry::new(name, value));
+    Ok(())
+}
+
+fn remove(entries: Option<&mut Vec<Entry>>, kind: &str, name: &str) -> anyhow::Result<()> {
+    let entries = entries.ok_or_else(|| anyhow!("{} `{}` is not found", kind, name))?;
+    let idx = format::position(entries, name)
+        .ok_or_else(|| anyhow!("{} `{}` is not found", kind, name))?;
+    entries.remove(idx);
+    Ok(())
+}
+
+/// Mapping by the path, which is created if it is missing
+fn mapping<'a>(document: &'a mut Document, path: &[&str]) -> anyhow::Result<&'a mut Vec<Entry>> {
+    document
+        .mapping_mut(path, true)
+        .ok_or_else(|| anyhow!("`{}` is not a mapping", path.join(".")))
+}
+
+fn add(document: &mut Document, kind: &str, args: &ArgMatches) -> anyhow::Result<()> {
+    let name = args.value_of("name").unwrap();
+
+    match kind {
+        UPSTREAM => {
+            let port: u16 = parse_required_arg(args, "port", "--port")?;
+            let mut fields = vec![Entry::new("port", Value::Scalar(port.to_string()))];
+            if let Some(host) = args.value_of("host") {
+                fields.push(Entry::new("host", scalar(host)));
+            }
+            if let Some(profiles) = profiles(args) {
+                fields.push(Entry::new("profiles", profiles));
+            }
+
+            let upstreams = mapping(document, &["upstreams"])?;
+            insert(upstreams, UPSTREAM, name, Value::Mapping(fields))
+        }
+        HANDLER => {
+            let mount_point = args.value_of("mount_point").unwrap();
+            let priority: u16 = parse_required_arg(args, "priority", "--priority")?;
+            let mut fields = vec![
+                Entry::new("kind", scalar(args.value_of("kind").unwrap())),
+                Entry::new("priority", Value::Scalar(priority.to_string())),
+            ];
+            if let Some(upstream) = args.value_of("upstream") {
+                fields.push(Entry::new("upstream", scalar(upstream)));
+            }
+            if let Some(profiles) = profiles(args) {
+                fields.push(Entry::new("profiles", profiles));
+            }
+            if let Some(dir) = args.value_of("dir") {
+                fields.push(Entry::new("dir", scalar(dir)));
+            }
+
+            let handlers = mapping(document, &["mount-points", mount_point, "handlers"])?;
+            insert(handlers, HANDLER, name, Value::Mapping(fields))
+        }
+        MOUNT_POINT => {
+            let mount_points = mapping(document, &["mount-points"])?;
+            insert(
+                mount_points,
+                MOUNT_POINT,
+                name,
+                Value::Mapping(vec![Entry::new("handlers", Value::Mapping(vec![]))]),
+            )
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn remove_item(document: &mut Document, kind: &str, args: &ArgMatches) -> anyhow::Result<()> {
+    let name = args.value_of("name").unwrap();
+
+    match kind {
+        UPSTREAM => remove(document.mapping_mut(&["upstreams"], false), UPSTREAM, name),
+        HANDLER => {
+            let mount_point = args.value_of("mount_point").unwrap();
+            remove(
+                document.mapping_mut(&["mount-points", mount_point, "handlers"], false),
+                HANDLER,
+                name,
+            )
+        }
+        MOUNT_POINT => remove(
+            document.mapping_mut(&["mount-points"], false),
+            MOUNT_POINT,
+            name,
+        ),
+        _ => unreachable!(),
+    }
+}
+
+fn edit(args: &ArgMatches, f: impl FnOnce(&mut Document, &str, &ArgMatches) -> anyhow::Result<()>) {
+    let (kind, args) = match args.subcommand() {
+        (kind, Some(args)) => (kind, args),
+        _ => return,
+    };
+
+    let path = args.value_of("config").unwrap();
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("could not read {}", path))
+        .map_err(CliError::config)
+        .or_exit();
+
+    let edited = (|| {
+        let exofile = Exofile::parse(&content).context("could not parse config")?;
+        let mut document = format::parse(&content)?;
+        f(&mut document, kind, args)?;
+        let printed = format::keep_line_endings(&content, format::print(&document));
+        bump_revision(&printed, &exofile)
+    })()
+    .map_err(CliError::config)
+    .or_exit();
+
+    let diagnostics = validate(&edited);
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        for error in errors {
+            eprintln!("{}", error.display(path));
+        }
+        eprintln!("{} is not changed, since the result is not valid", path);
+        std::process::exit(CONFIG_ERROR_EXIT_CODE);
+    }
+
+    print_diff(path, &content, &edited);
+
+    if !args.is_present("dry_run") {
+        atomic_file::write(Path::new(path), edited.as_bytes(), None)
+            .map_err(CliError::config)
+            .or_exit();
+    }
+
+    std::process::exit(0);
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    if let Some(app) = args.subcommand_matches(ADD_SUBCOMMAND) {
+        edit(app, add);
+    }
+    if let Some(app) = args.subcommand_matches(REMOVE_SUBCOMMAND) {
+        edit(app, remove_item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+---
+version: 1.1.0
+revision: 1
+name: test
+mount-points: {}
+upstreams:
+  backend:
+    port: 3000
+";
+
+    fn run_add(content: &str, args: &[&str]) -> anyhow::Result<String> {
+        let matches = add_subcommand()
+            .get_matches_from_safe(std::iter::once("add").chain(args.iter().copied()))
+            .unwrap();
+        let (kind, args) = matches.subcommand();
+
+        let mut document = format::parse(content)?;
+        add(&mut document, kind, args.unwrap())?;
+        Ok(format::print(&document))
+    }
+
+    #[test]
+    fn add_handler_to_new_mount_point() {
+        let content = run_add(CONFIG, &["mount-point", "api"]).unwrap();
+        assert_eq!(
+            content,
+            "\
+---
+version: 1.1.0
+revision: 1
+name: test
+mount-points:
+  api:
+    handlers: {}
+upstreams:
+  backend:
+    port: 3000
+"
+        );
+
+        let content = run_add(
+            &content,
+            &[
+                "handler",
+                "main",
+                "--mount-point",
+                "api",
+                "--kind",
+                "proxy",
+                "--upstream",
+                "backend",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            content,
+            "\
+---
+version: 1.1.0
+revision: 1
+name: test
+mount-points:
+  api:
+    handlers:
+      main:
+        kind: proxy
+        priority: 50
+        upstream: backend
+upstreams:
+  backend:
+    port: 3000
+"
+        );
+    }
+
+    #[test]
+    fn add_to_missing_mapping() {
+        let content = run_add(
+            "---\nversion: 1.1.0\nrevision: 1\nname: test\n",
+            &["upstream", "backend", "--port", "3000"],
+        )
+        .unwrap();
+
+        assert!(content.ends_with("upstreams:\n  backend:\n    port: 3000\n"));
+    }
+
+    #[test]
+    fn add_to_scalar_is_error() {
+        let error = run_add(
+            "---\nversion: 1.1.0\nrevision: 1\nname: test\nupstreams: 3000\n",
+            &["upstream", "backend", "--port", "3000"],
+        )
+        .unwrap_err();
+
+        assert_eq!(error.to_string(), "`upstreams` is not a mapping");
+    }
+}
@@ -0,0 +1,959 @@
+//! Canonical formatting of Exofile, which keeps comments.
+//!
+//! The file is parsed into a tree of block mappings and sequences, where each
+//! entry carries its comments. Scalars and flow collections are kept as text,
+//! only the quoting is normalized. The tree is reordered and printed with
+//! the 2-space indentation. The result is checked to be equal to the
+//! original file, so that formatting never changes the config.
+
+use crate::{
+    atomic_file,
+    config::{add_config_arg, print_diff},
+    error::{CliError, OrExit, CHECK_FAILED_EXIT_CODE},
+};
+use anyhow::{anyhow, bail, Context};
+use clap::{App, Arg, ArgMatches};
+use std::{collections::BTreeMap, fs, path::Path};
+
+const SUBCOMMAND: &str = "fmt";
+const INDENT: usize = 2;
+
+pub fn fmt_subcommand() -> App<'static, 'static> {
+    add_config_arg(App::new(SUBCOMMAND))
+        .about("Rewrite Exofile in the canonical format")
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .help("Do not write the file, exit with non-zero code if it is not formatted"),
+        )
+}
+
+/// Line of the file after splitting off `- ` of sequence items, so that
+/// `- key: value` becomes the dash and `key: value` with the greater indent
+#[derive(Debug, Default)]
+struct Line {
+    number: usize,
+    indent: usize,
+    is_dash: bool,
+    text: String,
+    comment: Option<String>,
+    leading_comments: Vec<String>,
+    blank_before: bool,
+    /// Content of the block scalar (`|` or `>`), relative to its indent
+    block: Vec<String>,
+}
+
+#[derive(Debug)]
+pub(super) enum Value {
+    Scalar(String),
+    Block(String, Vec<String>),
+    Mapping(Vec<Entry>),
+    Sequence(Vec<Entry>),
+}
+
+/// Mapping entry, or sequence item if `key` is `None`
+#[derive(Debug)]
+pub(super) struct Entry {
+    key: Option<String>,
+    value: Value,
+    comment: Option<String>,
+    leading_comments: Vec<String>,
+    blank_before: bool,
+}
+
+#[derive(Debug)]
+pub(super) struct Document {
+    /// Comments before `---`
+    header_comments: Vec<String>,
+    has_start: bool,
+    /// Comments at the top, which are separated from the first key by the
+    /// blank line, so they are not moved with it
+    top_comments: Vec<String>,
+    root: Option<Value>,
+    trailing_comments: Vec<String>,
+}
+
+/// Position of `#`, which starts the comment, i.e. is not in quotes
+fn comment_start(text: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut prev = ' ';
+    let mut is_escaped = false;
+
+    for (idx, c) in text.char_indices() {
+        match quote {
+            Some('"') if is_escaped => is_escaped = false,
+            Some('"') if c == '\\' => is_escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if (c == '"' || c == '\'') && (prev.is_whitespace() || "[{,:".contains(prev)) => {
+                quote = Some(c)
+            }
+            None if c == '#' && prev.is_whitespace() => return Some(idx),
+            None => {}
+        }
+        prev = c;
+    }
+
+    None
+}
+
+/// Split `key: value`. Returns `None` if the text is not a mapping entry.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+
+    for (idx, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if idx == 0 && (c == '"' || c == '\'') => quote = Some(c),
+            None if c == '[' || c == '{' => return None,
+            None if c == ':' => {
+                let rest = &text[idx + 1..];
+                if rest.is_empty() || rest.starts_with(' ') {
+                    return Some((&text[..idx], rest.trim()));
+                }
+            }
+            None => {}
+        }
+    }
+
+    None
+}
+
+fn is_block_indicator(value: &str) -> bool {
+    let mut chars = value.chars();
+    matches!(chars.next(), Some('|') | Some('>'))
+        && chars.all(|c| c == '-' || c == '+' || c.is_ascii_digit())
+}
+
+struct SplitLines {
+    header_comments: Vec<String>,
+    has_start: bool,
+    lines: Vec<Line>,
+    trailing_comments: Vec<String>,
+}
+
+fn split_lines(content: &str) -> anyhow::Result<SplitLines> {
+    let raw_lines = content.lines().collect::<Vec<_>>();
+    let mut lines: Vec<Line> = vec![];
+    let mut header_comments = vec![];
+    let mut has_start = false;
+    let mut leading_comments: Vec<String> = vec![];
+    let mut blank_before = false;
+    let mut idx = 0;
+
+    while idx < raw_lines.len() {
+        let raw = raw_lines[idx].trim_end();
+        let number = idx + 1;
+        idx += 1;
+
+        let trimmed = raw.trim_start();
+        if trimmed.is_empty() {
+            match leading_comments.last() {
+                // blank line inside or after the comment block is kept as is
+                Some(last) if !last.is_empty() => leading_comments.push(String::new()),
+                Some(_) => {}
+                None => blank_before = !lines.is_empty(),
+            }
+            continue;
+        }
+        if trimmed.starts_with('#') {
+            leading_comments.push(trimmed.to_string());
+            continue;
+        }
+        if trimmed == "---" {
+            if !lines.is_empty() || has_start {
+                bail!("line {}: multiple documents are not supported", number);
+            }
+            has_start = true;
+            header_comments = std::mem::take(&mut leading_comments);
+            blank_before = false;
+            continue;
+        }
+        if raw.starts_with('\t') {
+            bail!("line {}: tabs are not allowed for indentation", number);
+        }
+
+        let mut indent = raw.len() - trimmed.len();
+        let (mut text, comment) = match comment_start(trimmed) {
+            Some(pos) => (trimmed[..pos].trim_end(), Some(trimmed[pos..].to_string())),
+            None => (trimmed, None),
+        };
+
+        let mut line = Line {
+            number,
+            leading_comments: std::mem::take(&mut leading_comments),
+            blank_before,
+            ..Default::default()
+        };
+        blank_before = false;
+
+        while text == "-" || text.starts_with("- ") {
+            lines.push(Line {
+                indent,
+                is_dash: true,
+                ..std::mem::replace(
+                    &mut line,
+                    Line {
+                        number,
+                        ..Default::default()
+                    },
+                )
+            });
+            let rest = &text[1..];
+            indent += 1 + rest.len() - rest.trim_start().len();
+            text = rest.trim_start();
+        }
+
+        if text.is_empty() {
+            lines.last_mut().unwrap().comment = comment;
+            continue;
+        }
+
+        line.indent = indent;
+        line.text = text.to_string();
+        line.comment = comment;
+
+        let value = split_key(text).map(|(_, value)| value).unwrap_or(text);
+        if is_block_indicator(value) {
+            let block_lines = raw_lines[idx..]
+                .iter()
+                .take_while(|l| l.trim().is_empty() || l.len() - l.trim_start().len() > indent)
+                .count();
+            let mut block = raw_lines[idx..idx + block_lines].to_vec();
+            while block.last().map(|l| l.trim().is_empty()) == Some(true) {
+                block.pop();
+            }
+            idx += block.len();
+
+            let block_indent = block
+                .iter()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| l.len() - l.trim_start().len())
+                .min()
+                .unwrap_or(0);
+            line.block = block
+                .iter()
+                .map(|l| l.get(block_indent..).unwrap_or("").trim_end().to_string())
+                .collect();
+        }
+
+        lines.push(line);
+    }
+
+    for comments in [&mut header_comments, &mut leading_comments].iter_mut() {
+        while comments.last().map(|c| c.is_empty()) == Some(true) {
+            comments.pop();
+        }
+    }
+
+    Ok(SplitLines {
+        header_comments,
+        has_start,
+        lines,
+        trailing_comments: leading_comments,
+    })
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Line> {
+        self.lines.get(self.pos)
+    }
+
+    fn parse_node(&mut self) -> anyhow::Result<Value> {
+        let line = self.peek().unwrap();
+        let indent = line.indent;
+
+        if line.is_dash {
+            self.parse_sequence(indent)
+        } else {
+            self.parse_mapping(indent)
+        }
+    }
+
+    /// Parse value of the entry, which is either on the same line, or is the
+    /// nested node
+    fn parse_value(
+        &mut self,
+        value: &str,
+        block: Vec<String>,
+        indent: usize,
+    ) -> anyhow::Result<Value> {
+        if is_block_indicator(value) {
+            return Ok(Value::Block(value.to_string(), block));
+        }
+        // empty flow collections are printed as such, so they are parsed
+        // as collections to be editable
+        if value == "{}" {
+            return Ok(Value::Mapping(vec![]));
+        }
+        if value == "[]" {
+            return Ok(Value::Sequence(vec![]));
+        }
+        if !value.is_empty() {
+            return Ok(Value::Scalar(normalize_scalar(value)));
+        }
+
+        match self.peek() {
+            Some(next) if next.indent > indent || (next.is_dash && next.indent == indent) => {
+                self.parse_node()
+            }
+            _ => Ok(Value::Scalar(String::new())),
+        }
+    }
+
+    fn parse_mapping(&mut self, indent: usize) -> anyhow::Result<Value> {
+        let mut entries = vec![];
+
+        while let Some(line) = self.peek() {
+            if line.indent < indent || line.is_dash {
+                break;
+            }
+            if line.indent > indent {
+                bail!("line {}: bad indentation", line.number);
+            }
+
+            let line = std::mem::take(&mut self.lines[self.pos]);
+            self.pos += 1;
+
+            let (key, value) = split_key(&line.text)
+                .ok_or_else(|| anyhow!("line {}: expected `key: value`", line.number))?;
+
+            // sequences may have the same indent as the key
+            let value = self.parse_value(value, line.block.clone(), indent)?;
+
+            entries.push(Entry {
+                key: Some(key.to_string()),
+                value,
+                comment: line.comment,
+                leading_comments: line.leading_comments,
+                blank_before: line.blank_before,
+            });
+        }
+
+        Ok(Value::Mapping(entries))
+    }
+
+    fn parse_sequence(&mut self, indent: usize) -> anyhow::Result<Value> {
+        let mut items = vec![];
+
+        while let Some(line) = self.peek() {
+            if line.indent != indent || !line.is_dash {
+                if line.indent > indent {
+                    bail!("line {}: bad indentation", line.number);
+                }
+                break;
+            }
+
+            let line = std::mem::take(&mut self.lines[self.pos]);
+            self.pos += 1;
+
+            let value = match self.peek() {
+                Some(next)
+                    if next.indent > indent && !next.is_dash && next.number == line.number =>
+                {
+                    // `- scalar` or `- key: value`
+                    if split_key(&next.text).is_some() {
+                        self.parse_node()?
+                    } else {
+                        let next = std::mem::take(&mut self.lines[self.pos]);
+                        self.pos += 1;
+                        let value = self.parse_value(&next.text, next.block, next.indent)?;
+                        items.push(Entry {
+                            key: None,
+                            value,
+                            comment: next.comment,
+                            leading_comments: line.leading_comments,
+                            blank_before: line.blank_before,
+                        });
+                        continue;
+                    }
+                }
+                Some(next) if next.indent > indent => self.parse_node()?,
+                _ => Value::Scalar(String::new()),
+            };
+
+            items.push(Entry {
+                key: None,
+                value,
+                comment: line.comment,
+                leading_comments: line.leading_comments,
+                blank_before: line.blank_before,
+            });
+        }
+
+        Ok(Value::Sequence(items))
+    }
+}
+
+pub(super) fn parse(content: &str) -> anyhow::Result<Document> {
+    let split = split_lines(content)?;
+    let mut parser = Parser {
+        lines: split.lines,
+        pos: 0,
+    };
+
+    let mut root = match parser.peek() {
+        Some(_) => Some(parser.parse_node()?),
+        None => None,
+    };
+
+    if let Some(line) = parser.peek() {
+        bail!("line {}: unexpected content", line.number);
+    }
+
+    let mut top_comments = vec![];
+    if let Some(Value::Mapping(entries)) = &mut root {
+        if let Some(first) = entries.first_mut() {
+            if first.leading_comments.last().map(|c| c.is_empty()) == Some(true) {
+                top_comments = std::mem::take(&mut first.leading_comments);
+                top_comments.pop();
+            }
+        }
+    }
+
+    Ok(Document {
+        header_comments: split.header_comments,
+        has_start: split.has_start,
+        top_comments,
+        root,
+        trailing_comments: split.trailing_comments,
+    })
+}
+
+/// Use double quotes for quoted strings, and normalize spaces in flow
+/// sequences, e.g. `['a',  "b"]` becomes `["a", "b"]`
+fn normalize_scalar(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        let unquoted = value[1..value.len() - 1].replace("''", "'");
+        return format!(
+            "\"{}\"",
+            unquoted.replace('\\', r"\\").replace('"', r#"\""#)
+        );
+    }
+
+    if value.starts_with('[') && value.ends_with(']') {
+        if let Some(items) = split_flow_sequence(&value[1..value.len() - 1]) {
+            let items = items
+                .iter()
+                .map(|item| normalize_scalar(item))
+                .collect::<Vec<_>>();
+            return format!("[{}]", items.join(", "));
+        }
+    }
+
+    value.to_string()
+}
+
+/// Split flow sequence of scalars. Returns `None` for nested collections,
+/// which are kept as is.
+fn split_flow_sequence(inner: &str) -> Option<Vec<&str>> {
+    let mut items = vec![];
+    let mut quote = None;
+    let mut start = 0;
+
+    for (idx, c) in inner.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if "[]{}".contains(c) => return None,
+            None if c == ',' => {
+                items.push(inner[start..idx].trim());
+                start = idx + 1;
+            }
+            None => {}
+        }
+    }
+
+    let last = inner[start..].trim();
+    if !last.is_empty() {
+        items.push(last);
+    }
+
+    if items.iter().any(|item| item.is_empty()) {
+        return None;
+    }
+
+    Some(items)
+}
+
+impl Entry {
+    pub(super) fn new(key: &str, value: Value) -> Self {
+        Entry {
+            key: Some(key.to_string()),
+            value,
+            comment: None,
+            leading_comments: vec![],
+            blank_before: false,
+        }
+    }
+}
+
+impl Document {
+    /// Mapping by the path of keys. Missing mappings are created if `create`
+    /// is set.
+    pub(super) fn mapping_mut(&mut self, path: &[&str], create: bool) -> Option<&mut Vec<Entry>> {
+        match &mut self.root {
+            Some(Value::Mapping(entries)) => mapping_at(entries, path, create),
+            _ => None,
+        }
+    }
+}
+
+fn mapping_at<'a>(
+    entries: &'a mut Vec<Entry>,
+    path: &[&str],
+    create: bool,
+) -> Option<&'a mut Vec<Entry>> {
+    let (key, rest) = match path.split_first() {
+        Some(r) => r,
+        None => return Some(entries),
+    };
+
+    let idx = match position(entries, key) {
+        Some(idx) => idx,
+        None if create => {
+            entries.push(Entry::new(key, Value::Mapping(vec![])));
+            entries.len() - 1
+        }
+        None => return None,
+    };
+
+    let value = &mut entries[idx].value;
+    if create && matches!(value, Value::Scalar(s) if s.is_empty()) {
+        *value = Value::Mapping(vec![]);
+    }
+
+    match value {
+        Value::Mapping(entries) => mapping_at(entries, rest, create),
+        _ => None,
+    }
+}
+
+/// Position of the entry with the key
+pub(super) fn position(entries: &[Entry], key: &str) -> Option<usize> {
+    entries
+        .iter()
+        .position(|e| e.key.as_deref().map(unquote) == Some(key))
+}
+
+fn unquote(key: &str) -> &str {
+    key.trim_matches(|c| c == '"' || c == '\'')
+}
+
+fn entries_mut(value: &mut Value) -> Option<&mut Vec<Entry>> {
+    match value {
+        Value::Mapping(entries) => Some(entries),
+        _ => None,
+    }
+}
+
+fn find_mut<'a>(entries: &'a mut Vec<Entry>, key: &str) -> Option<&'a mut Value> {
+    entries
+        .iter_mut()
+        .find(|e| e.key.as_deref().map(unquote) == Some(key))
+        .map(|e| &mut e.value)
+}
+
+/// Put known keys first in the given order, others keep the original order
+fn order_keys(entries: &mut Vec<Entry>, known: &[&str]) {
+    entries.sort_by_key(|e| {
+        let key = e.key.as_deref().map(unquote).unwrap_or("");
+        known.iter().position(|k| *k == key).unwrap_or(known.len())
+    });
+}
+
+fn sort_by_name(entries: &mut Vec<Entry>) {
+    entries.sort_by(|a, b| {
+        a.key
+            .as_deref()
+            .map(unquote)
+            .cmp(&b.key.as_deref().map(unquote))
+    });
+}
+
+fn priority(handler: &Entry) -> u64 {
+    match &handler.value {
+        Value::Mapping(entries) => entries
+            .iter()
+            .find(|e| e.key.as_deref().map(unquote) == Some("priority"))
+            .and_then(|e| match &e.value {
+                Value::Scalar(s) => s.parse().ok(),
+                _ => None,
+            })
+            .unwrap_or(u64::MAX),
+        _ => u64::MAX,
+    }
+}
+
+/// Apply the canonical order: top-level keys in the order of importance,
+/// mount points and upstreams by name, handlers by priority and name
+fn reorder(root: &mut Value) {
+    let root = match entries_mut(root) {
+        Some(root) => root,
+        None => return,
+    };
+
+    order_keys(
+        root,
+        &["version", "revision", "name", "mount-points", "upstreams"],
+    );
+
+    if let Some(mount_points) = find_mut(root, "mount-points").and_then(entries_mut) {
+        sort_by_name(mount_points);
+
+        for mount_point in mount_points.iter_mut() {
+            let mount_point = match entries_mut(&mut mount_point.value) {
+                Some(mount_point) => mount_point,
+                None => continue,
+            };
+            order_keys(mount_point, &["handlers"]);
+
+            if let Some(handlers) = find_mut(mount_point, "handlers").and_then(entries_mut) {
+                sort_by_name(handlers);
+                handlers.sort_by_key(priority);
+
+                for handler in handlers.iter_mut() {
+                    if let Some(handler) = entries_mut(&mut handler.value) {
+                        order_keys(handler, &["kind", "priority", "upstream", "profiles"]);
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(upstreams) = find_mut(root, "upstreams").and_then(entries_mut) {
+        sort_by_name(upstreams);
+
+        for upstream in upstreams.iter_mut() {
+            if let Some(upstream) = entries_mut(&mut upstream.value) {
+                order_keys(upstream, &["port", "host", "profiles"]);
+            }
+        }
+    }
+}
+
+struct Printer {
+    out: String,
+}
+
+impl Printer {
+    fn line(&mut self, indent: usize, text: &str, comment: &Option<String>) {
+        self.out.push_str(&" ".repeat(indent));
+        self.out.push_str(text);
+        if let Some(comment) = comment {
+            self.out.push(' ');
+            self.out.push_str(comment);
+        }
+        self.out.push('\n');
+    }
+
+    fn comments(&mut self, indent: usize, comments: &[String]) {
+        for comment in comments {
+            if comment.is_empty() {
+                self.out.push('\n');
+            } else {
+                self.line(indent, comment, &None);
+            }
+        }
+    }
+
+    fn block(&mut self, indent: usize, block: &[String]) {
+        for line in block {
+            if line.is_empty() {
+                self.out.push('\n');
+            } else {
+                self.line(indent, line, &None);
+            }
+        }
+    }
+
+    /// Print entries. The first one is printed after `- ` if `is_item` is
+    /// set, since it starts the sequence item.
+    fn entries(&mut self, indent: usize, entries: &[Entry], is_item: bool) {
+        for (idx, entry) in entries.iter().enumerate() {
+            let is_inline = is_item && idx == 0;
+
+            if idx > 0 && entry.blank_before {
+                self.out.push('\n');
+            }
+            if !is_inline {
+                self.comments(indent, &entry.leading_comments);
+            }
+
+            let (prefix_indent, prefix) = if is_inline {
+                (indent - INDENT, "- ")
+            } else {
+                (indent, "")
+            };
+
+            let head = match &entry.key {
+                Some(key) => format!("{}{}:", prefix, key),
+                None => "-".to_string(),
+            };
+            self.value(prefix_indent, indent, &head, &entry.value, &entry.comment);
+        }
+    }
+
+    fn value(
+        &mut self,
+        head_indent: usize,
+        indent: usize,
+        head: &str,
+        value: &Value,
+        comment: &Option<String>,
+    ) {
+        match value {
+            Value::Scalar(s) if s.is_empty() => self.line(head_indent, head, comment),
+            Value::Scalar(s) => self.line(head_indent, &format!("{} {}", head, s), comment),
+            Value::Block(header, block) => {
+                self.line(head_indent, &format!("{} {}", head, header), comment);
+                self.block(indent + INDENT, block);
+            }
+            Value::Mapping(entries) if entries.is_empty() => {
+                self.line(head_indent, &format!("{} {{}}", head), comment)
+            }
+            Value::Mapping(entries) => {
+                self.line(head_indent, head, comment);
+                self.entries(indent + INDENT, entries, false);
+            }
+            Value::Sequence(items) if items.is_empty() => {
+                self.line(head_indent, &format!("{} []", head), comment)
+            }
+            Value::Sequence(items) => {
+                self.line(head_indent, head, comment);
+                self.items(indent + INDENT, items);
+            }
+        }
+    }
+
+    fn items(&mut self, indent: usize, items: &[Entry]) {
+        for (idx, item) in items.iter().enumerate() {
+            if idx > 0 && item.blank_before {
+                self.out.push('\n');
+            }
+            self.comments(indent, &item.leading_comments);
+
+            match &item.value {
+                Value::Mapping(entries) if !entries.is_empty() && item.comment.is_none() => {
+                    self.comments(indent, &entries[0].leading_comments);
+                    self.entries(indent + INDENT, entries, true);
+                }
+                value => self.value(indent, indent, "-", value, &item.comment),
+            }
+        }
+    }
+}
+
+pub(super) fn print(document: &Document) -> String {
+    let mut printer = Printer { out: String::new() };
+
+    printer.comments(0, &document.header_comments);
+    if document.has_start {
+        printer.out.push_str("---\n");
+    }
+    if !document.top_comments.is_empty() {
+        printer.comments(0, &document.top_comments);
+        printer.out.push('\n');
+    }
+
+    if let Some(Value::Mapping(entries)) = &document.root {
+        printer.entries(0, entries, false);
+    }
+
+    if !document.trailing_comments.is_empty() && document.root.is_some() {
+        printer.out.push('\n');
+    }
+    printer.comments(0, &document.trailing_comments);
+
+    printer.out
+}
+
+/// Representation of YAML value, which does not depend on the order of
+/// mapping keys, to compare the formatted file with the original one
+#[derive(Debug, PartialEq)]
+enum Canonical {
+    Scalar(String),
+    Sequence(Vec<Canonical>),
+    Mapping(BTreeMap<String, Canonical>),
+}
+
+fn canonical(value: serde_yaml::Value) -> Canonical {
+    match value {
+        serde_yaml::Value::Sequence(items) => {
+            Canonical::Sequence(items.into_iter().map(canonical).collect())
+        }
+        serde_yaml::Value::Mapping(mapping) => Canonical::Mapping(
+            mapping
+                .into_iter()
+                .map(|(k, v)| (format!("{:?}", k), canonical(v)))
+                .collect(),
+        ),
+        value => Canonical::Scalar(format!("{:?}", value)),
+    }
+}
+
+/// Printer outputs `\n`, so line endings of the original file are restored
+pub(super) fn keep_line_endings(original: &str, printed: String) -> String {
+    if original.contains("\r\n") {
+        printed.replace('\n', "\r\n")
+    } else {
+        printed
+    }
+}
+
+pub fn format(content: &str) -> anyhow::Result<String> {
+    let original: serde_yaml::Value =
+        serde_yaml::from_str(content).context("could not parse config")?;
+
+    let mut document = parse(content)?;
+    match &mut document.root {
+        Some(root @ Value::Mapping(_)) => reorder(root),
+        _ => bail!("config should be a mapping"),
+    }
+    let formatted = print(&document);
+
+    let result: serde_yaml::Value = serde_yaml::from_str(&formatted)
+        .context("formatted config could not be parsed, please report the issue")?;
+    if canonical(original) != canonical(result) {
+        bail!("formatting changes the config, please report the issue");
+    }
+
+    Ok(keep_line_endings(content, formatted))
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    if let Some(app) = args.subcommand_matches(SUBCOMMAND) {
+        let path = app.value_of("config").unwrap();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path))
+            .map_err(CliError::config)
+            .or_exit();
+
+        let formatted = format(&content)
+            .with_context(|| format!("could not format {}", path))
+            .map_err(CliError::config)
+            .or_exit();
+
+        if formatted == content {
+            std::process::exit(0);
+        }
+
+        if app.is_present("check") {
+            print_diff(path, &content, &formatted);
+            eprintln!("{} is not formatted", path);
+            std::process::exit(CHECK_FAILED_EXIT_CODE);
+        }
+
+        atomic_file::write(Path::new(path), formatted.as_bytes(), None)
+            .map_err(CliError::config)
+            .or_exit();
+        println!("{} is formatted", path);
+        std::process::exit(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Exofiles generated by `exogress init`
+    fn templates() -> Vec<(&'static str, String)> {
+        vec![
+            (
+                "laravel-artisan",
+                include_str!("../../platforms_templates/laravel-artisan/Exofile.yml").to_string(),
+            ),
+            (
+                "proxy",
+                include_str!("../../platforms_templates/proxy/Exofile.yml.handlebars")
+                    .replace("{{ this.port }}", "3000"),
+            ),
+            (
+                "rails",
+                include_str!("../../platforms_templates/rails/Exofile.yml").to_string(),
+            ),
+            (
+                "svelte",
+                include_str!("../../platforms_templates/svelte/Exofile.yml").to_string(),
+            ),
+            (
+                "synapse-admin",
+                include_str!("../../platforms_templates/synapse/synapse-admin/Exofile.yml")
+                    .to_string(),
+            ),
+            (
+                "synapse-server",
+                include_str!("../../platforms_templates/synapse/synapse-server/Exofile.yml")
+                    .to_string(),
+            ),
+        ]
+    }
+
+    fn comments(content: &str) -> Vec<&str> {
+        content
+            .lines()
+            .filter_map(|line| {
+                let trimmed = line.trim();
+                comment_start(trimmed).map(|pos| &trimmed[pos..])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn templates_are_formatted_idempotently() {
+        for (name, content) in templates() {
+            let formatted = format(&content).unwrap_or_else(|e| panic!("{}: {:#}", name, e));
+            let reformatted = format(&formatted).unwrap_or_else(|e| panic!("{}: {:#}", name, e));
+
+            assert_eq!(formatted, reformatted, "{}", name);
+        }
+    }
+
+    #[test]
+    fn templates_keep_comments() {
+        for (name, content) in templates() {
+            let formatted = format(&content).unwrap();
+            let formatted_comments = comments(&formatted);
+
+            for comment in comments(&content) {
+                assert!(
+                    formatted_comments.contains(&comment),
+                    "{}: comment `{}` is lost",
+                    name,
+                    comment
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn crlf_is_kept() {
+        let content = "---\r\nversion: 1.0.0\r\n# comment\r\nname: test\r\n";
+
+        let formatted = format(content).unwrap();
+
+        assert_eq!(
+            formatted.matches("\r\n").count(),
+            formatted.matches('\n').count()
+        );
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn templates_keep_value() {
+        for (name, content) in templates() {
+            let formatted = format(&content).unwrap();
+
+            let original: serde_yaml::Value = serde_yaml::from_str(&content).unwrap();
+            let result: serde_yaml::Value = serde_yaml::from_str(&formatted).unwrap();
+            assert_eq!(canonical(original), canonical(result), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::{
+    atomic_file,
+    config::{add_config_arg, current_version, print_diff, set_top_level_value},
+    error::{CliError, OrExit, CHECK_FAILED_EXIT_CODE},
+    exofile::Exofile,
+};
+use anyhow::{anyhow, bail, Context};
+use clap::{App, Arg, ArgMatches};
+use semver::Version;
+use std::{fs, path::Path};
+
+const SUBCOMMAND: &str = "migrate";
+
+pub fn migrate_subcommand() -> App<'static, 'static> {
+    add_config_arg(App::new(SUBCOMMAND))
+        .about("Upgrade Exofile to the current config version")
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .help("Do not write the file, exit with non-zero code if migration is needed"),
+        )
+}
+
+/// Step, which brings configs of the major version to the current one.
+/// Steps edit the text, so that comments and formatting are kept, and
+/// report deprecated constructs as warnings.
+struct Migration {
+    from_major: u64,
+    migrate: fn(&str, &mut Vec<String>) -> anyhow::Result<String>,
+}
+
+/// 1.x configs have the same structure as the current version, only
+/// `version` is updated for them
+fn identity(content: &str, _warnings: &mut Vec<String>) -> anyhow::Result<String> {
+    Ok(content.to_string())
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    from_major: 1,
+    migrate: identity,
+}];
+
+/// Version and revision are read without parsing the whole config, since
+/// outdated configs may be rejected by `ClientConfig`
+fn version_and_revision(content: &str) -> anyhow::Result<(Version, u64)> {
+    let document: serde_yaml::Value =
+        serde_yaml::from_str(content).context("could not parse config")?;
+
+    let version = document
+        .get("version")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("version is not found"))?;
+    let version = Version::parse(version).with_context(|| format!("bad version `{}`", version))?;
+    let revision = document
+        .get("revision")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| anyhow!("revision is not found"))?;
+
+    Ok((version, revision))
+}
+
+/// Returns the migrated config and warnings, or `None` if the config is up
+/// to date
+pub fn migrate(content: &str) -> anyhow::Result<Option<(String, Vec<String>)>> {
+    let (version, revision) = version_and_revision(content)?;
+    let current = current_version()?;
+
+    if version > current {
+        bail!(
+            "config version {} is newer than {} supported by this client. Upgrade exogress",
+            version,
+            current
+        );
+    }
+    if version == current {
+        return Ok(None);
+    }
+
+    let migration = MIGRATIONS
+        .iter()
+        .find(|migration| migration.from_major == version.major)
+        .ok_or_else(|| anyhow!("config version {} can't be migrated", version))?;
+
+    let mut warnings = vec![];
+    let migrated = (migration.migrate)(content, &mut warnings)
+        .with_context(|| format!("could not migrate from {}", version))?;
+
+    let migrated = set_top_level_value(&migrated, "version", &current.to_string())
+        .ok_or_else(|| anyhow!("version is not found"))?;
+    let migrated = set_top_level_value(&migrated, "revision", &(revision + 1).to_string())
+        .ok_or_else(|| anyhow!("revision is not found"))?;
+
+    Exofile::parse(&migrated).context("migrated config is not valid, please report the issue")?;
+
+    Ok(Some((migrated, warnings)))
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    if let Some(app) = args.subcommand_matches(SUBCOMMAND) {
+        let path = app.value_of("config").unwrap();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path))
+            .map_err(CliError::config)
+            .or_exit();
+        let current = current_version().map_err(CliError::config).or_exit();
+
+        let (migrated, warnings) = match migrate(&content).map_err(CliError::config).or_exit() {
+            Some(r) => r,
+            None => {
+                println!("{} is up to date", path);
+                std::process::exit(0);
+            }
+        };
+
+        print_diff(path, &content, &migrated);
+        for warning in &warnings {
+            eprintln!("{}: warning: {}", path, warning);
+        }
+
+        if app.is_present("check") {
+            eprintln!("{} should be migrated to {}", path, current);
+            std::process::exit(CHECK_FAILED_EXIT_CODE);
+        }
+
+        atomic_file::write(Path::new(path), migrated.as_bytes(), None)
+            .map_err(CliError::config)
+            .or_exit();
+        println!("{} is migrated to {}", path, current);
+        std::process::exit(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(version: &str) -> String {
+        format!(
+            "---\nversion: {} # config version\nrevision: 7\nname: test\nmount-points: {{}}\n",
+            version
+        )
+    }
+
+    #[test]
+    fn up_to_date() {
+        let current = current_version().unwrap();
+
+        assert!(migrate(&config(&current.to_string())).unwrap().is_none());
+    }
+
+    #[test]
+    fn outdated() {
+        let current = current_version().unwrap();
+        assert!(current > Version::new(1, 0, 0));
+
+        let (migrated, warnings) = migrate(&config("1.0.0")).unwrap().unwrap();
+
+        assert_eq!(
+            migrated,
+            format!(
+                "---\nversion: {} # config version\nrevision: 8\nname: test\nmount-points: {{}}\n",
+                current
+            )
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn newer_than_supported() {
+        let current = current_version().unwrap();
+        let newer = Version::new(current.major + 1, 0, 0);
+
+        let e = migrate(&config(&newer.to_string())).unwrap_err();
+
+        assert!(e.to_string().contains("newer"), "{}", e);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::exofile::Exofile;
+use anyhow::Context;
+use clap::{App, AppSettings, Arg, ArgMatches};
+use exogress_common::config_core::{CURRENT_VERSION, DEFAULT_CONFIG_FILE};
+use semver::Version;
+use similar::TextDiff;
+use std::fmt;
+
+pub mod edit;
+pub mod format;
+pub mod migrate;
+pub mod schema;
+pub mod show;
+pub mod test;
+pub mod validate;
+
+pub fn add_config_arg<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a> {
+    app.arg(
+        Arg::with_name("config")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .help("Config file")
+            .env("EXG_CONFIG_FILE")
+            .default_value(DEFAULT_CONFIG_FILE)
+            .takes_value(true),
+    )
+}
+
+pub fn config_app() -> App<'static, 'static> {
+    App::new("config")
+        .about("Inspect and edit Exofile")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(validate::validate_subcommand())
+        .subcommand(show::show_subcommand())
+        .subcommand(migrate::migrate_subcommand())
+        .subcommand(schema::schema_subcommand())
+        .subcommand(format::fmt_subcommand())
+        .subcommand(edit::add_subcommand())
+        .subcommand(edit::remove_subcommand())
+        .subcommand(test::test_subcommand())
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    validate::handle_subcommand(args);
+    show::handle_subcommand(args);
+    migrate::handle_subcommand(args);
+    schema::handle_subcommand(args);
+    format::handle_subcommand(args);
+    edit::handle_subcommand(args);
+    test::handle_subcommand(args);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Problem in the config file, printed in the `file:line:column` format
+/// understood by editors and CI tools
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(location: Option<(usize, usize)>, message: impl fmt::Display) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            location,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn warning(location: Option<(usize, usize)>, message: impl fmt::Display) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            location,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn display<'a>(&'a self, path: &'a str) -> impl fmt::Display + 'a {
+        DisplayDiagnostic {
+            diagnostic: self,
+            path,
+        }
+    }
+}
+
+struct DisplayDiagnostic<'a> {
+    diagnostic: &'a Diagnostic,
+    path: &'a str,
+}
+
+impl<'a> fmt::Display for DisplayDiagnostic<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.diagnostic.location {
+            Some((line, column)) => write!(f, "{}:{}:{}: ", self.path, line, column)?,
+            None => write!(f, "{}: ", self.path)?,
+        }
+        write!(
+            f,
+            "{}: {}",
+            self.diagnostic.severity, self.diagnostic.message
+        )
+    }
+}
+
+/// Find line and column (both starting from 1) of the key by its path in
+/// the YAML document, e.g. `["mount-points", "default", "handlers"]`. Only
+/// block mappings are supported, which is enough to point at Exofile items.
+/// Keys of list items are looked up as keys of the list, the first item with
+/// the key is taken.
+pub fn locate(content: &str, path: &[&str]) -> Option<(usize, usize)> {
+    let mut lines = content.lines().enumerate();
+    let mut parent_indent = None;
+    let mut location = None;
+
+    for key in path {
+        let mut child_indent = None;
+
+        loop {
+            let (idx, line) = lines.next()?;
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("---") {
+                continue;
+            }
+            let trimmed = match trimmed.strip_prefix("- ") {
+                Some(item) => item.trim_start(),
+                None => trimmed,
+            };
+
+            let indent = line.len() - trimmed.len();
+            if matches!(parent_indent, Some(parent) if indent <= parent) {
+                // left the parent mapping without finding the key
+                return None;
+            }
+            if indent > *child_indent.get_or_insert(indent) {
+                // nested in the preceding key
+                continue;
+            }
+
+            let name = trimmed
+                .split_once(':')
+                .map(|(name, _)| name.trim().trim_matches(|c| c == '"' || c == '\''));
+            if name == Some(*key) {
+                parent_indent = Some(indent);
+                location = Some((idx + 1, indent + 1));
+                break;
+            }
+        }
+    }
+
+    location
+}
+
+/// Replace the value of the top-level key, keeping the rest of the line,
+/// e.g. the comment. Returns `None` if there is no such key.
+pub fn set_top_level_value(content: &str, key: &str, value: &str) -> Option<String> {
+    let mut is_found = false;
+
+    let lines = content
+        .split_inclusive('\n')
+        .map(|line| {
+            let rest = match line.strip_prefix(key).and_then(|l| l.strip_prefix(':')) {
+                Some(rest) if !is_found => rest,
+                _ => return line.to_string(),
+            };
+            is_found = true;
+
+            let comment = rest.find(" #").map(|idx| &rest[idx..]).unwrap_or("");
+            let comment = comment.trim_end_matches(&['\r', '\n'][..]);
+            let line_ending = &line[line.trim_end_matches(&['\r', '\n'][..]).len()..];
+
+            format!("{}: {}{}{}", key, value, comment, line_ending)
+        })
+        .collect::<String>();
+
+    if is_found {
+        Some(lines)
+    } else {
+        None
+    }
+}
+
+/// Config version supported by the client
+pub fn current_version() -> anyhow::Result<Version> {
+    let version = CURRENT_VERSION.0.to_string();
+    Version::parse(&version).with_context(|| format!("bad current config version `{}`", version))
+}
+
+/// Increment `revision`, since the cloud distinguishes configs by it
+pub fn bump_revision(content: &str, exofile: &Exofile) -> anyhow::Result<String> {
+    set_top_level_value(content, "revision", &(exofile.revision + 1).to_string())
+        .ok_or_else(|| anyhow::anyhow!("revision is not found"))
+}
+
+/// Print changes in the unified diff format
+pub fn print_diff(path: &str, old: &str, new: &str) {
+    let diff = TextDiff::from_lines(old, new);
+    print!(
+        "{}",
+        diff.unified_diff()
+            .header(&format!("a/{}", path), &format!("b/{}", path))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+---
+version: 1.0.0
+revision: 1
+name: test
+mount-points:
+  default:
+    handlers:
+      # comment with upstream: backend
+      main:
+        kind: proxy
+        priority: 10
+        upstream: backend
+        rules:
+          - filter:
+              path: [\"*\"]
+            action: invoke
+          - filter:
+              path: [\"api\", \"*\"]
+            action: respond
+upstreams:
+  backend:
+    port: 3000
+";
+
+    #[test]
+    fn top_level_key() {
+        assert_eq!(locate(CONFIG, &["name"]), Some((4, 1)));
+        assert_eq!(locate(CONFIG, &["upstreams"]), Some((20, 1)));
+    }
+
+    #[test]
+    fn nested_key() {
+        assert_eq!(
+            locate(
+                CONFIG,
+                &["mount-points", "default", "handlers", "main", "upstream"]
+            ),
+            Some((12, 9))
+        );
+        assert_eq!(
+            locate(CONFIG, &["upstreams", "backend", "port"]),
+            Some((22, 5))
+        );
+    }
+
+    #[test]
+    fn key_is_not_found_outside_of_parent() {
+        assert_eq!(locate(CONFIG, &["mount-points", "backend"]), None);
+        assert_eq!(locate(CONFIG, &["mount-points", "default", "port"]), None);
+    }
+
+    #[test]
+    fn deeper_keys_are_not_children() {
+        assert_eq!(locate(CONFIG, &["mount-points", "handlers"]), None);
+        assert_eq!(
+            locate(CONFIG, &["mount-points", "default", "upstream"]),
+            None
+        );
+    }
+
+    #[test]
+    fn keys_in_list_items() {
+        let rules = ["mount-points", "default", "handlers", "main", "rules"];
+
+        assert_eq!(
+            locate(CONFIG, &[&rules[..], &["filter"]].concat()),
+            Some((14, 13))
+        );
+        assert_eq!(
+            locate(CONFIG, &[&rules[..], &["action"]].concat()),
+            Some((16, 13))
+        );
+        assert_eq!(
+            locate(CONFIG, &[&rules[..], &["filter", "path"]].concat()),
+            Some((15, 15))
+        );
+    }
+
+    #[test]
+    fn quoted_keys() {
+        let content = "\"mount-points\":\n  'default':\n    handlers: {}\n";
+
+        assert_eq!(locate(content, &["mount-points", "default"]), Some((2, 3)));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::error::{CliError, OrExit};
+use anyhow::Context;
+use clap::{App, Arg, ArgMatches};
+use exogress_common::config_core::{ClientConfig, CURRENT_VERSION};
+use std::fs;
+
+const SUBCOMMAND: &str = "schema";
+
+pub fn schema_subcommand() -> App<'static, 'static> {
+    App::new(SUBCOMMAND)
+        .about("Print JSON Schema of Exofile for the current config version")
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Write schema to the file instead of stdout")
+                .takes_value(true),
+        )
+}
+
+pub fn schema() -> String {
+    let mut schema = schemars::schema_for!(ClientConfig);
+    let metadata = schema.schema.metadata();
+    metadata.title = Some("Exofile".to_string());
+    metadata.description = Some(format!(
+        "Exogress config, version {}. See https://developer.exogress.com/",
+        CURRENT_VERSION.0
+    ));
+
+    serde_json::to_string_pretty(&schema).unwrap()
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    if let Some(app) = args.subcommand_matches(SUBCOMMAND) {
+        let schema = schema();
+
+        match app.value_of("output") {
+            Some(path) => {
+                fs::write(path, schema + "\n")
+                    .with_context(|| format!("could not write {}", path))
+                    .map_err(CliError::config)
+                    .or_exit();
+            }
+            None => println!("{}", schema),
+        }
+
+        std::process::exit(0);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{
+    config::add_config_arg,
+    error::{parse_arg, parse_required_arg, CliError, OrExit},
+    exofile::{Exofile, Handler, Upstream},
+};
+use clap::{App, Arg, ArgMatches};
+use exogress_common::entities::ProfileName;
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+const SUBCOMMAND: &str = "show";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Yaml,
+    Json,
+}
+
+impl OutputFormat {
+    pub const VARIANTS: &'static [&'static str] = &["table", "yaml", "json"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(anyhow::anyhow!("unknown output format `{}`", s)),
+        }
+    }
+}
+
+pub fn show_subcommand() -> App<'static, 'static> {
+    add_config_arg(App::new(SUBCOMMAND))
+        .about("Print configuration, which is active in the profile")
+        .arg(
+            Arg::with_name("profile")
+                .short("p")
+                .long("profile")
+                .help("Profile name")
+                .env("EXG_PROFILE")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FORMAT")
+                .help("Output format")
+                .possible_values(OutputFormat::VARIANTS)
+                .default_value("table")
+                .takes_value(true),
+        )
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct EffectiveHandler<'a> {
+    name: &'a str,
+
+    #[serde(flatten)]
+    handler: &'a Handler,
+}
+
+/// Config as `spawn` sees it: handlers and upstreams of other profiles are
+/// filtered out, and handlers are ordered by priority
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct EffectiveConfig<'a> {
+    version: &'a str,
+    revision: u64,
+    name: &'a str,
+    profile: Option<&'a str>,
+    mount_points: BTreeMap<&'a str, Vec<EffectiveHandler<'a>>>,
+    upstreams: BTreeMap<&'a str, &'a Upstream>,
+}
+
+impl<'a> EffectiveConfig<'a> {
+    fn new(exofile: &'a Exofile, profile: Option<&'a str>) -> Self {
+        EffectiveConfig {
+            version: &exofile.version,
+            revision: exofile.revision,
+            name: &exofile.name,
+            profile,
+            mount_points: exofile
+                .mount_points
+                .iter()
+                .map(|(mount_point_name, mount_point)| {
+                    let handlers = exofile
+                        .active_handlers(mount_point, profile)
+                        .into_iter()
+                        .map(|(name, handler)| EffectiveHandler { name, handler })
+                        .collect();
+                    (mount_point_name.as_str(), handlers)
+                })
+                .collect(),
+            upstreams: exofile
+                .active_upstreams(profile)
+                .map(|(name, upstream)| (name.as_str(), upstream))
+                .collect(),
+        }
+    }
+}
+
+struct Table(Vec<Vec<String>>);
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let columns = self.0.iter().map(|row| row.len()).max().unwrap_or(0);
+        let widths = (0..columns)
+            .map(|column| {
+                self.0
+                    .iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+
+        for row in &self.0 {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for EffectiveConfig<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Config:  {} (version {}, revision {})",
+            self.name, self.version, self.revision
+        )?;
+        writeln!(f, "Profile: {}", self.profile.unwrap_or("<none>"))?;
+        writeln!(f)?;
+
+        let mut handlers = vec![vec![
+            "MOUNT POINT".to_string(),
+            "PRIORITY".to_string(),
+            "HANDLER".to_string(),
+            "KIND".to_string(),
+            "UPSTREAM".to_string(),
+        ]];
+        for (mount_point_name, mount_point_handlers) in &self.mount_points {
+            for EffectiveHandler { name, handler } in mount_point_handlers {
+                handlers.push(vec![
+                    mount_point_name.to_string(),
+                    handler.priority.to_string(),
+                    name.to_string(),
+                    handler.kind.clone(),
+                    handler.upstream.clone().unwrap_or_else(|| "-".to_string()),
+                ]);
+            }
+        }
+        write!(f, "{}", Table(handlers))?;
+        writeln!(f)?;
+
+        let mut upstreams = vec![vec!["UPSTREAM".to_string(), "ADDRESS".to_string()]];
+        for (name, upstream) in &self.upstreams {
+            upstreams.push(vec![
+                name.to_string(),
+                format!("{}:{}", upstream.host(), upstream.port),
+            ]);
+        }
+        write!(f, "{}", Table(upstreams))
+    }
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    if let Some(app) = args.subcommand_matches(SUBCOMMAND) {
+        let path = app.value_of("config").unwrap();
+        // validated the same way as in `spawn`
+        let _: Option<ProfileName> = parse_arg(app, "profile", "--profile (EXG_PROFILE)").or_exit();
+        let profile = app.value_of("profile");
+        let output_format: OutputFormat = parse_required_arg(app, "output", "--output").or_exit();
+
+        let exofile = Exofile::load(path).map_err(CliError::config).or_exit();
+        let effective = EffectiveConfig::new(&exofile, profile);
+
+        match output_format {
+            OutputFormat::Table => print!("{}", effective),
+            OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&effective).unwrap()),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&effective).unwrap()),
+        }
+
+        std::process::exit(0);
+    }
+}
@@ -0,0 +1,548 @@
+use crate::{
+    config::add_config_arg,
+    error::{parse_arg, CliError, OrExit, CHECK_FAILED_EXIT_CODE},
+    exofile::Exofile,
+    gateway::{
+        default_mount_point, facts, render, static_dir_path, AfterInvoke, Outcome, Request, Route,
+        Selection, StaticResponse,
+    },
+};
+use anyhow::{anyhow, Context};
+use clap::{App, Arg, ArgMatches};
+use exogress_common::entities::ProfileName;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const SUBCOMMAND: &str = "test";
+
+pub fn test_subcommand() -> App<'static, 'static> {
+    add_config_arg(App::new(SUBCOMMAND))
+        .about("Show how the request is routed by rules, or check test cases")
+        .arg(
+            Arg::with_name("path")
+                .value_name("PATH")
+                .help("Request path, e.g. /index.php")
+                .required_unless("cases")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("method")
+                .short("X")
+                .long("method")
+                .value_name("METHOD")
+                .help("Request method")
+                .default_value("GET")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("host")
+                .long("host")
+                .value_name("HOST")
+                .help("Hostname of the mount point, available as `mount_point_hostname` fact")
+                .default_value("example.com")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("accept")
+                .long("accept")
+                .value_name("ACCEPT")
+                .help("Accept header of the request")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("respond")
+                .long("respond")
+                .value_name("HANDLER=STATUS")
+                .help("Status code of the handler, if invoked [default: 200, static-dir checks files]")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cases")
+                .long("cases")
+                .value_name("FILE")
+                .help("YAML file with test cases and expected results")
+                .conflicts_with("path")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mount_point")
+                .long("mount-point")
+                .value_name("NAME")
+                .help("Mount point [default: `default`, or the only one]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .short("p")
+                .long("profile")
+                .help("Profile name")
+                .env("EXG_PROFILE")
+                .required(false)
+                .takes_value(true),
+        )
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+fn default_host() -> String {
+    "example.com".to_string()
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Expectation {
+    handler: Option<String>,
+    action: Option<String>,
+    status: Option<u16>,
+    body_contains: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct TestCase {
+    name: Option<String>,
+
+    #[serde(default = "default_method")]
+    method: String,
+
+    path: String,
+
+    #[serde(default = "default_host")]
+    host: String,
+
+    #[serde(default)]
+    accept: Option<String>,
+
+    #[serde(default)]
+    mount_point: Option<String>,
+
+    #[serde(default)]
+    profile: Option<String>,
+
+    /// Status codes of handlers, if they are invoked
+    #[serde(default)]
+    responses: BTreeMap<String, u16>,
+
+    #[serde(default)]
+    expect: Expectation,
+}
+
+/// Result of the simulated request
+#[derive(Debug)]
+struct Simulation {
+    mount_point: String,
+    trace: Vec<String>,
+    /// `invoke`, `respond`, `exception` or `not-found`
+    action: &'static str,
+    handler: Option<String>,
+    status: u16,
+    body: Option<String>,
+}
+
+struct Simulator<'a> {
+    exofile: &'a Exofile,
+    config_dir: &'a Path,
+}
+
+impl<'a> Simulator<'a> {
+    fn respond(
+        &self,
+        case: &TestCase,
+        response: &StaticResponse,
+        status_code: Option<u16>,
+    ) -> anyhow::Result<(u16, Option<String>)> {
+        let rendered = render(
+            response,
+            status_code,
+            case.accept.as_deref(),
+            &facts(&case.host, "https"),
+        )?;
+        Ok((rendered.status_code, Some(rendered.body)))
+    }
+
+    fn run(&self, case: &TestCase) -> anyhow::Result<Simulation> {
+        let mount_point = match &case.mount_point {
+            Some(mount_point) => mount_point.as_str(),
+            None => default_mount_point(self.exofile)?,
+        };
+        let route = Route::new(self.exofile, mount_point, case.profile.as_deref())?;
+        let request = Request::new(&case.method, &case.path);
+
+        let mut trace = vec![];
+        let mut start = 0;
+        let mut last_handler = None;
+
+        let (action, status, body) = loop {
+            match route.select(&request, start, &mut trace) {
+                Selection::Invoke { handler, rule } => {
+                    let route_handler = &route.handlers[handler];
+                    last_handler = Some(route_handler.name.to_string());
+
+                    let status = match case.responses.get(route_handler.name) {
+                        Some(status) => *status,
+                        None if route_handler.handler.kind == "static-dir" => {
+                            match static_dir_path(self.config_dir, route_handler, &request)? {
+                                Some(_) => 200,
+                                None => 404,
+                            }
+                        }
+                        None => 200,
+                    };
+
+                    match route.after_invoke(
+                        handler,
+                        rule,
+                        &Outcome::StatusCode(status),
+                        &mut trace,
+                    ) {
+                        AfterInvoke::Done => break ("invoke", status, None),
+                        AfterInvoke::NextHandler => start = handler + 1,
+                        AfterInvoke::Respond {
+                            response,
+                            status_code,
+                        } => {
+                            let (status, body) = self.respond(case, response, status_code)?;
+                            break ("respond", status, body);
+                        }
+                        AfterInvoke::Exception(e) => break ("exception", 500, Some(e)),
+                    }
+                }
+                Selection::Respond {
+                    handler,
+                    response,
+                    status_code,
+                } => {
+                    last_handler = handler.map(|h| route.handlers[h].name.to_string());
+                    let (status, body) = self.respond(case, response, status_code)?;
+                    break ("respond", status, body);
+                }
+                Selection::Exception(e) => break ("exception", 500, Some(e)),
+                Selection::NotFound => {
+                    last_handler = None;
+                    break ("not-found", 404, None);
+                }
+            }
+        };
+
+        Ok(Simulation {
+            mount_point: mount_point.to_string(),
+            trace,
+            action,
+            handler: last_handler,
+            status,
+            body,
+        })
+    }
+}
+
+fn print_simulation(request: &str, simulation: &Simulation) {
+    println!("Request:     {}", request);
+    println!("Mount point: {}", simulation.mount_point);
+    for step in &simulation.trace {
+        println!("  {}", step);
+    }
+    match &simulation.handler {
+        Some(handler) => println!(
+            "Result:      {} by handler `{}`, status {}",
+            simulation.action, handler, simulation.status
+        ),
+        None => println!(
+            "Result:      {}, status {}",
+            simulation.action, simulation.status
+        ),
+    }
+    if let Some(body) = &simulation.body {
+        println!("Body:\n{}", body);
+    }
+}
+
+/// Returns the list of mismatches with the expectation
+fn check(expect: &Expectation, simulation: &Simulation) -> Vec<String> {
+    let mut mismatches = vec![];
+
+    if let Some(handler) = &expect.handler {
+        if simulation.handler.as_ref() != Some(handler) {
+            mismatches.push(format!(
+                "expected handler `{}`, got {}",
+                handler,
+                simulation
+                    .handler
+                    .as_ref()
+                    .map(|h| format!("`{}`", h))
+                    .unwrap_or_else(|| "none".to_string())
+            ));
+        }
+    }
+    if let Some(action) = &expect.action {
+        if action != simulation.action {
+            mismatches.push(format!(
+                "expected action {}, got {}",
+                action, simulation.action
+            ));
+        }
+    }
+    if let Some(status) = expect.status {
+        if status != simulation.status {
+            mismatches.push(format!(
+                "expected status {}, got {}",
+                status, simulation.status
+            ));
+        }
+    }
+    if let Some(body_contains) = &expect.body_contains {
+        let body = simulation.body.as_deref().unwrap_or("");
+        if !body.contains(body_contains.as_str()) {
+            mismatches.push(format!("expected body to contain `{}`", body_contains));
+        }
+    }
+
+    mismatches
+}
+
+fn parse_responses(args: &ArgMatches) -> anyhow::Result<BTreeMap<String, u16>> {
+    args.values_of("respond")
+        .into_iter()
+        .flatten()
+        .map(|respond| {
+            let (handler, status) = respond
+                .split_once('=')
+                .ok_or_else(|| anyhow!("bad --respond `{}`, expected HANDLER=STATUS", respond))?;
+            let status = status
+                .parse()
+                .with_context(|| format!("bad status in --respond `{}`", respond))?;
+            Ok((handler.to_string(), status))
+        })
+        .collect()
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    if let Some(app) = args.subcommand_matches(SUBCOMMAND) {
+        let config_path = PathBuf::from(app.value_of("config").unwrap());
+        let _: Option<ProfileName> = parse_arg(app, "profile", "--profile (EXG_PROFILE)").or_exit();
+
+        let exofile = Exofile::load(&config_path)
+            .map_err(CliError::config)
+            .or_exit();
+        let simulator = Simulator {
+            exofile: &exofile,
+            config_dir: config_path.parent().unwrap_or_else(|| Path::new(".")),
+        };
+
+        if let Some(path) = app.value_of("path") {
+            let case = TestCase {
+                name: None,
+                method: app.value_of("method").unwrap().to_string(),
+                path: path.to_string(),
+                host: app.value_of("host").unwrap().to_string(),
+                accept: app.value_of("accept").map(|a| a.to_string()),
+                mount_point: app.value_of("mount_point").map(|m| m.to_string()),
+                profile: app.value_of("profile").map(|p| p.to_string()),
+                responses: parse_responses(app)
+                    .map_err(|e| CliError::usage("--respond", e))
+                    .or_exit(),
+                expect: Default::default(),
+            };
+
+            let simulation = simulator.run(&case).map_err(CliError::config).or_exit();
+            print_simulation(&format!("{} {}", case.method, case.path), &simulation);
+            std::process::exit(0);
+        }
+
+        let cases_path = app.value_of("cases").unwrap();
+        let cases: Vec<TestCase> = fs::read_to_string(cases_path)
+            .with_context(|| format!("could not read {}", cases_path))
+            .and_then(|content| {
+                serde_yaml::from_str(&content)
+                    .with_context(|| format!("could not parse {}", cases_path))
+            })
+            .map_err(CliError::config)
+            .or_exit();
+
+        let mut failed = 0;
+        for (idx, mut case) in cases.into_iter().enumerate() {
+            if case.profile.is_none() {
+                case.profile = app.value_of("profile").map(|p| p.to_string());
+            }
+            let name = case
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("#{} {} {}", idx + 1, case.method, case.path));
+
+            let mismatches = match simulator.run(&case) {
+                Ok(simulation) => {
+                    let mismatches = check(&case.expect, &simulation);
+                    if !mismatches.is_empty() {
+                        for step in &simulation.trace {
+                            eprintln!("    {}", step);
+                        }
+                    }
+                    mismatches
+                }
+                Err(e) => vec![format!("{:#}", e)],
+            };
+
+            if mismatches.is_empty() {
+                println!("ok      {}", name);
+            } else {
+                failed += 1;
+                println!("FAILED  {}", name);
+                for mismatch in mismatches {
+                    println!("    {}", mismatch);
+                }
+            }
+        }
+
+        if failed > 0 {
+            println!("{} test case(s) failed", failed);
+            std::process::exit(CHECK_FAILED_EXIT_CODE);
+        }
+
+        std::process::exit(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAILS: &str = include_str!("../../platforms_templates/rails/Exofile.yml");
+    const LARAVEL: &str = include_str!("../../platforms_templates/laravel-artisan/Exofile.yml");
+
+    /// Handler, which rescues the thrown exception with a static response,
+    /// and rules, which don't match every request
+    const RESCUE: &str = "\
+---
+version: 1.1.0
+revision: 1
+name: rescue
+mount-points:
+  default:
+    handlers:
+      main:
+        kind: proxy
+        priority: 10
+        upstream: backend
+        rules:
+          - filter:
+              path: [\"admin\", \"*\"]
+            action: throw
+            exception: forbidden
+          - filter:
+              path: [\"api\", \"*\"]
+            action: invoke
+        rescue:
+          - catch: \"exception:forbidden\"
+            action: respond
+            static-response: forbidden
+static-responses:
+  forbidden:
+    kind: raw
+    status-code: 403
+    body:
+      - content-type: text/plain
+        content: Forbidden
+upstreams:
+  backend:
+    port: 3000
+";
+
+    /// Config dir with `public/robots.txt`
+    fn config_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "exogress-simulator-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(dir.join("public")).unwrap();
+        fs::write(dir.join("public").join("robots.txt"), "").unwrap();
+        dir
+    }
+
+    fn simulate(config: &str, config_dir: &Path, path: &str) -> Simulation {
+        let exofile = Exofile::parse(config).unwrap();
+        let simulator = Simulator {
+            exofile: &exofile,
+            config_dir,
+        };
+        let case = TestCase {
+            name: None,
+            method: default_method(),
+            path: path.to_string(),
+            host: default_host(),
+            accept: None,
+            mount_point: None,
+            profile: None,
+            responses: Default::default(),
+            expect: Default::default(),
+        };
+
+        simulator.run(&case).unwrap()
+    }
+
+    #[test]
+    fn invoke() {
+        let dir = config_dir("invoke");
+
+        let simulation = simulate(RAILS, &dir, "/robots.txt");
+        assert_eq!(simulation.action, "invoke");
+        assert_eq!(simulation.handler.as_deref(), Some("public"));
+        assert_eq!(simulation.status, 200);
+
+        let simulation = simulate(LARAVEL, &dir, "/index.php");
+        assert_eq!(simulation.action, "invoke");
+        assert_eq!(simulation.handler.as_deref(), Some("laravel"));
+
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn rescued_status_code() {
+        let dir = config_dir("rescued-status-code");
+
+        // the file is not found, so the next handler is invoked
+        let simulation = simulate(RAILS, &dir, "/users");
+        assert_eq!(simulation.action, "invoke");
+        assert_eq!(simulation.handler.as_deref(), Some("rails-server"));
+
+        // files under assets are not rescued
+        let simulation = simulate(RAILS, &dir, "/assets/app.js");
+        assert_eq!(simulation.action, "invoke");
+        assert_eq!(simulation.handler.as_deref(), Some("public"));
+        assert_eq!(simulation.status, 404);
+
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn rescued_exception() {
+        let dir = config_dir("rescued-exception");
+
+        let simulation = simulate(RESCUE, &dir, "/admin/users");
+        assert_eq!(simulation.action, "respond");
+        assert_eq!(simulation.handler.as_deref(), Some("main"));
+        assert_eq!(simulation.status, 403);
+        assert_eq!(simulation.body.as_deref(), Some("Forbidden"));
+
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn not_found() {
+        let dir = config_dir("not-found");
+
+        let simulation = simulate(RESCUE, &dir, "/users");
+        assert_eq!(simulation.action, "not-found");
+        assert_eq!(simulation.handler, None);
+        assert_eq!(simulation.status, 404);
+
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{
+    config::{add_config_arg, current_version, locate, Diagnostic, Severity},
+    error::{CliError, OrExit, CONFIG_ERROR_EXIT_CODE},
+    exofile::{is_profile_active, Exofile},
+};
+use clap::{App, ArgMatches};
+use std::fs;
+
+const SUBCOMMAND: &str = "validate";
+
+pub fn validate_subcommand() -> App<'static, 'static> {
+    add_config_arg(App::new(SUBCOMMAND)).about("Validate Exofile without connecting to the cloud")
+}
+
+fn check_version(exofile: &Exofile, content: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let location = locate(content, &["version"]);
+    let current = match current_version() {
+        Ok(current) => current,
+        Err(e) => {
+            diagnostics.push(Diagnostic::error(location, format!("{:#}", e)));
+            return;
+        }
+    };
+
+    match semver::Version::parse(&exofile.version) {
+        Ok(version) if version > current => diagnostics.push(Diagnostic::error(
+            location,
+            format!(
+                "config version {} is not supported, the latest supported is {}. Upgrade exogress",
+                version, current
+            ),
+        )),
+        Ok(version) if version < current => diagnostics.push(Diagnostic::warning(
+            location,
+            format!(
+                "config version {} is outdated, the current is {}. Run `exogress config migrate`",
+                version, current
+            ),
+        )),
+        Ok(_) => {}
+        Err(e) => diagnostics.push(Diagnostic::error(
+            location,
+            format!("bad version `{}`: {}", exofile.version, e),
+        )),
+    }
+}
+
+fn check_references(exofile: &Exofile, content: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let profiles = exofile.profiles();
+    // `None` stands for running without a profile
+    let all_profiles = std::iter::once(None)
+        .chain(profiles.iter().map(|p| Some(*p)))
+        .collect::<Vec<_>>();
+
+    for (mount_point_name, mount_point) in &exofile.mount_points {
+        for (handler_name, handler) in &mount_point.handlers {
+            let handler_path = [
+                "mount-points",
+                mount_point_name.as_str(),
+                "handlers",
+                handler_name.as_str(),
+            ];
+
+            let upstream_name = match &handler.upstream {
+                Some(upstream_name) => upstream_name,
+                None => {
+                    if handler.kind == "proxy" {
+                        diagnostics.push(Diagnostic::error(
+                            locate(content, &handler_path),
+                            format!("proxy handler `{}` has no upstream", handler_name),
+                        ));
+                    }
+                    continue;
+                }
+            };
+
+            let location = locate(content, &[&handler_path[..], &["upstream"][..]].concat())
+                .or_else(|| locate(content, &handler_path));
+
+            let upstream = match exofile.upstreams.get(upstream_name) {
+                Some(upstream) => upstream,
+                None => {
+                    diagnostics.push(Diagnostic::error(
+                        location,
+                        format!(
+                            "handler `{}` refers to upstream `{}`, which is not defined in upstreams",
+                            handler_name, upstream_name
+                        ),
+                    ));
+                    continue;
+                }
+            };
+
+            for profile in &all_profiles {
+                if is_profile_active(&handler.profiles, *profile)
+                    && !is_profile_active(&upstream.profiles, *profile)
+                {
+                    let profile = match profile {
+                        Some(profile) => format!("profile `{}`", profile),
+                        None => "no profile".to_string(),
+                    };
+                    diagnostics.push(Diagnostic::error(
+                        location,
+                        format!(
+                            "handler `{}` is active with {}, but its upstream `{}` is not",
+                            handler_name, profile, upstream_name
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    for upstream_name in exofile.upstreams.keys() {
+        let is_used = exofile
+            .mount_points
+            .values()
+            .flat_map(|mount_point| mount_point.handlers.values())
+            .any(|handler| handler.upstream.as_ref() == Some(upstream_name));
+
+        if !is_used {
+            diagnostics.push(Diagnostic::warning(
+                locate(content, &["upstreams", upstream_name.as_str()]),
+                format!("upstream `{}` is not used by any handler", upstream_name),
+            ));
+        }
+    }
+}
+
+fn parse_error(e: serde_yaml::Error) -> Diagnostic {
+    let location = e.location().map(|l| (l.line(), l.column()));
+    Diagnostic::error(location, e)
+}
+
+/// Validate the config offline. Parse errors are returned as a single
+/// diagnostic, since nothing else may be checked after them.
+pub fn validate(content: &str) -> Vec<Diagnostic> {
+    let exofile = match Exofile::parse(content) {
+        Ok(exofile) => exofile,
+        Err(e) => return vec![parse_error(e)],
+    };
+
+    let mut diagnostics = vec![];
+    check_version(&exofile, content, &mut diagnostics);
+    check_references(&exofile, content, &mut diagnostics);
+
+    diagnostics.sort_by_key(|d| d.location);
+    diagnostics
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    if let Some(app) = args.subcommand_matches(SUBCOMMAND) {
+        let path = app.value_of("config").unwrap();
+        let content = fs::read_to_string(path)
+            .map_err(|e| CliError::config(anyhow::anyhow!("could not read {}: {}", path, e)))
+            .or_exit();
+
+        let diagnostics = validate(&content);
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic.display(path));
+        }
+
+        let errors = diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count();
+        if errors > 0 {
+            eprintln!("{}: {} error(s) found", path, errors);
+            std::process::exit(CONFIG_ERROR_EXIT_CODE);
+        }
+
+        println!("{} is valid", path);
+        std::process::exit(0);
+    }
+}
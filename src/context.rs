@@ -0,0 +1,205 @@
+use crate::{
+    add_credentials_args, atomic_file,
+    error::{CliError, OrExit},
+};
+use anyhow::{anyhow, bail, Context as _};
+use clap::{App, AppSettings, Arg, ArgMatches};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Named set of credentials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CredentialsContext {
+    pub account: String,
+    pub project: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Credentials {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_context: Option<String>,
+
+    #[serde(default)]
+    pub contexts: BTreeMap<String, CredentialsContext>,
+}
+
+/// `EXG_CREDENTIALS_FILE` or `~/.config/exogress/credentials`
+pub fn credentials_path() -> anyhow::Result<PathBuf> {
+    if let Some(path) = std::env::var_os("EXG_CREDENTIALS_FILE") {
+        return Ok(path.into());
+    }
+
+    let home = dirs_next::home_dir().ok_or_else(|| anyhow!("could not find home directory"))?;
+
+    Ok(home.join(".config").join("exogress").join("credentials"))
+}
+
+impl Credentials {
+    pub fn load(path: &Path) -> anyhow::Result<Credentials> {
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("could not parse {}", path.display()))
+    }
+
+    /// Save atomically. The file is created readable by the owner only,
+    /// since it contains secrets
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        atomic_file::write(path, serde_yaml::to_string(self)?.as_bytes(), Some(0o600))
+    }
+
+    /// The context with the provided name, or the current one if no name
+    /// is provided
+    pub fn resolve(&self, name: Option<&str>) -> anyhow::Result<Option<&CredentialsContext>> {
+        match name.or_else(|| self.current_context.as_deref()) {
+            Some(name) => self
+                .contexts
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| anyhow!("context `{}` not found", name)),
+            None => Ok(None),
+        }
+    }
+}
+
+pub fn context_app() -> App<'static, 'static> {
+    App::new("context")
+        .about("Manage named credential contexts")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            add_credentials_args(
+                App::new("add").about("Add or replace context").arg(
+                    Arg::with_name("name")
+                        .help("Context name")
+                        .required(true)
+                        .index(1),
+                ),
+            )
+            .arg(
+                Arg::with_name("use")
+                    .long("use")
+                    .help("Make the context current")
+                    .takes_value(false)
+                    .required(false),
+            ),
+        )
+        .subcommand(
+            App::new("use").about("Make context current").arg(
+                Arg::with_name("name")
+                    .help("Context name")
+                    .required(true)
+                    .index(1),
+            ),
+        )
+        .subcommand(App::new("list").about("List contexts"))
+        .subcommand(
+            App::new("remove").about("Remove context").arg(
+                Arg::with_name("name")
+                    .help("Context name")
+                    .required(true)
+                    .index(1),
+            ),
+        )
+}
+
+fn required_value(args: &ArgMatches, name: &str, flag: &str) -> anyhow::Result<String> {
+    args.value_of(name)
+        .map(|v| v.to_string())
+        .ok_or_else(|| anyhow!("{} is required", flag))
+}
+
+fn handle(args: &ArgMatches) -> anyhow::Result<()> {
+    let path = credentials_path()?;
+    let mut credentials = Credentials::load(&path)?;
+
+    match args.subcommand() {
+        ("add", Some(add_args)) => {
+            let name = add_args.value_of("name").unwrap().to_string();
+
+            let context = CredentialsContext {
+                account: required_value(add_args, "account", "--account")?,
+                project: required_value(add_args, "project", "--project")?,
+                access_key_id: required_value(add_args, "access_key_id", "--access-key-id")?,
+                secret_access_key: required_value(
+                    add_args,
+                    "secret_access_key",
+                    "--secret-access-key",
+                )?,
+            };
+            context
+                .access_key_id
+                .parse::<exogress_common::entities::AccessKeyId>()
+                .map_err(|_| anyhow!("--access-key-id is not ULID"))?;
+
+            credentials.contexts.insert(name.clone(), context);
+            if add_args.is_present("use") || credentials.current_context.is_none() {
+                credentials.current_context = Some(name.clone());
+            }
+            credentials.save(&path)?;
+
+            println!("Context {} saved", name);
+        }
+        ("use", Some(use_args)) => {
+            let name = use_args.value_of("name").unwrap();
+            if !credentials.contexts.contains_key(name) {
+                bail!("context `{}` not found", name);
+            }
+
+            credentials.current_context = Some(name.to_string());
+            credentials.save(&path)?;
+
+            println!("Switched to context {}", name);
+        }
+        ("list", Some(_)) => {
+            for (name, context) in &credentials.contexts {
+                let marker = if credentials.current_context.as_deref() == Some(name.as_str()) {
+                    "*"
+                } else {
+                    " "
+                };
+                println!(
+                    "{} {} (account: {}, project: {})",
+                    marker, name, context.account, context.project
+                );
+            }
+        }
+        ("remove", Some(remove_args)) => {
+            let name = remove_args.value_of("name").unwrap();
+            if credentials.contexts.remove(name).is_none() {
+                bail!("context `{}` not found", name);
+            }
+            if credentials.current_context.as_deref() == Some(name) {
+                credentials.current_context = None;
+            }
+            credentials.save(&path)?;
+
+            println!("Context {} removed", name);
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    handle(args).map_err(CliError::config).or_exit();
+
+    std::process::exit(0);
+}
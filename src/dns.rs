@@ -0,0 +1,155 @@
+use crate::error::CliError;
+use anyhow::{anyhow, Context};
+use clap::{Arg, ArgMatches};
+use std::net::{IpAddr, SocketAddr};
+use trust_dns_resolver::{
+    config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+    TokioAsyncResolver, TokioHandle,
+};
+
+const DNS_SERVER_ARG: &str = "--dns-server (EXG_DNS_SERVERS)";
+
+pub fn add_args<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a> {
+    app.arg(
+        Arg::with_name("dns_server")
+            .long("dns-server")
+            .value_name("IP[:PORT]")
+            .help("DNS server to use instead of the system configuration")
+            .env("EXG_DNS_SERVERS")
+            .multiple(true)
+            .number_of_values(1)
+            .use_delimiter(true)
+            .required(false)
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("dns_protocol")
+            .long("dns-protocol")
+            .value_name("PROTOCOL")
+            .help("Protocol to query --dns-server with: plain DNS, DNS-over-TLS or DNS-over-HTTPS")
+            .env("EXG_DNS_PROTOCOL")
+            .possible_values(&["udp", "tls", "https"])
+            .default_value("udp")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("dns_tls_name")
+            .long("dns-tls-name")
+            .value_name("NAME")
+            .help("Name in the certificate of --dns-server, required for tls and https, e.g. cloudflare-dns.com")
+            .env("EXG_DNS_TLS_NAME")
+            .required_ifs(&[("dns_protocol", "tls"), ("dns_protocol", "https")])
+            .takes_value(true),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DnsProtocol {
+    Udp,
+    Tls,
+    Https,
+}
+
+impl DnsProtocol {
+    fn default_port(&self) -> u16 {
+        match self {
+            DnsProtocol::Udp => 53,
+            DnsProtocol::Tls => 853,
+            DnsProtocol::Https => 443,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DnsSettings {
+    servers: Vec<SocketAddr>,
+    protocol: DnsProtocol,
+    tls_name: Option<String>,
+}
+
+fn parse_server(s: &str, default_port: u16) -> anyhow::Result<SocketAddr> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let ip: IpAddr = s
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .map_err(|_| anyhow!("expected IP[:PORT]"))?;
+    Ok(SocketAddr::new(ip, default_port))
+}
+
+pub fn extract(matches: &ArgMatches) -> Result<DnsSettings, CliError> {
+    let protocol = match matches.value_of("dns_protocol") {
+        Some("tls") => DnsProtocol::Tls,
+        Some("https") => DnsProtocol::Https,
+        _ => DnsProtocol::Udp,
+    };
+
+    let servers = matches
+        .values_of("dns_server")
+        .into_iter()
+        .flatten()
+        .map(|server| {
+            parse_server(server, protocol.default_port()).map_err(|e| {
+                CliError::usage(DNS_SERVER_ARG, format!("bad value `{}`: {}", server, e))
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(DnsSettings {
+        servers,
+        protocol,
+        tls_name: matches.value_of("dns_tls_name").map(|n| n.to_string()),
+    })
+}
+
+fn upstream_config(settings: &DnsSettings) -> anyhow::Result<(ResolverConfig, ResolverOpts)> {
+    if settings.servers.is_empty() {
+        return trust_dns_resolver::system_conf::read_system_conf()
+            .context("could not read the system DNS configuration");
+    }
+
+    let mut group = NameServerConfigGroup::new();
+    for server in &settings.servers {
+        let ips = [server.ip()];
+        let tls_name = || {
+            settings
+                .tls_name
+                .clone()
+                .ok_or_else(|| anyhow!("--dns-tls-name is not set"))
+        };
+        group.merge(match settings.protocol {
+            DnsProtocol::Udp => NameServerConfigGroup::from_ips_clear(&ips, server.port(), true),
+            DnsProtocol::Tls => {
+                NameServerConfigGroup::from_ips_tls(&ips, server.port(), tls_name()?, true)
+            }
+            DnsProtocol::Https => {
+                NameServerConfigGroup::from_ips_https(&ips, server.port(), tls_name()?, true)
+            }
+        });
+    }
+
+    Ok((
+        ResolverConfig::from_parts(None, vec![], group),
+        ResolverOpts::default(),
+    ))
+}
+
+/// Resolver for the client.
+///
+/// Without `--dns-server` the system configuration is used. If it can't be
+/// read, e.g. in minimal containers without `/etc/resolv.conf`, DNS servers
+/// must be set explicitly.
+pub fn build_resolver(settings: &DnsSettings) -> Result<TokioAsyncResolver, CliError> {
+    let (config, opts) = upstream_config(settings).map_err(|e| {
+        if settings.servers.is_empty() {
+            CliError::config(anyhow!("{:#}. Set DNS servers with --dns-server", e))
+        } else {
+            CliError::usage(DNS_SERVER_ARG, format!("{:#}", e))
+        }
+    })?;
+
+    TokioAsyncResolver::new(config, opts, TokioHandle)
+        .map_err(|e| CliError::config(anyhow!("could not create DNS resolver: {}", e)))
+}
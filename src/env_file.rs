@@ -0,0 +1,283 @@
+use anyhow::{anyhow, bail, Context};
+use clap::{Arg, ArgMatches};
+use std::{fs, path::Path};
+
+pub const ARG_NAME: &str = "env_file";
+
+pub fn add_args<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a> {
+    app.arg(
+        Arg::with_name(ARG_NAME)
+            .long("env-file")
+            .value_name("FILE")
+            .help("Read environment variables from the file. Already set variables are not overridden")
+            .multiple(true)
+            .number_of_values(1)
+            .required(false)
+            .takes_value(true),
+    )
+}
+
+fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn ensure_only_comment(rest: &str) -> anyhow::Result<()> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(anyhow!("unexpected characters after the closing quote"))
+    }
+}
+
+fn expand(value: &str, defined: &[(String, String)]) -> String {
+    shellexpand::env_with_context_no_errors(value, |name| {
+        defined
+            .iter()
+            .rev()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+            .or_else(|| std::env::var(name).ok())
+    })
+    .into_owned()
+}
+
+/// Parse env file, following the dotenv rules:
+///
+/// - empty lines and lines starting with `#` are ignored
+/// - `export ` prefix is allowed
+/// - values in single quotes are taken literally
+/// - values in double quotes support escapes (`\n`, `\t`, `\"`, `\\`, `\$`),
+///   may span multiple lines and expand `$VAR` and `${VAR}`
+/// - unquoted values are trimmed, `#` after a whitespace starts a comment,
+///   variables are expanded
+pub fn parse(content: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut result: Vec<(String, String)> = Vec::new();
+    let mut lines = content.lines().enumerate();
+
+    while let Some((idx, line)) = lines.next() {
+        let line_no = idx + 1;
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let trimmed = trimmed
+            .strip_prefix("export ")
+            .map(|s| s.trim_start())
+            .unwrap_or(trimmed);
+
+        let (key, raw_value) = trimmed
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {}: expected KEY=VALUE", line_no))?;
+        let key = key.trim_end();
+
+        if !is_valid_key(key) {
+            bail!("line {}: bad variable name `{}`", line_no, key);
+        }
+
+        let raw_value = raw_value.trim_start();
+
+        let value = if let Some(quoted) = raw_value.strip_prefix('\'') {
+            let (value, rest) = quoted
+                .split_once('\'')
+                .ok_or_else(|| anyhow!("line {}: unterminated single quote", line_no))?;
+            ensure_only_comment(rest).with_context(|| format!("line {}", line_no))?;
+            value.to_string()
+        } else if let Some(quoted) = raw_value.strip_prefix('"') {
+            // escaped `$` is not expanded, so everything before it is
+            // expanded separately
+            let mut value = String::new();
+            let mut pending = String::new();
+            let mut current = quoted.to_string();
+
+            loop {
+                let mut chars = current.char_indices();
+                let mut closed_at = None;
+
+                while let Some((pos, c)) = chars.next() {
+                    match c {
+                        '"' => {
+                            closed_at = Some(pos);
+                            break;
+                        }
+                        '\\' => match chars.next() {
+                            Some((_, 'n')) => pending.push('\n'),
+                            Some((_, 'r')) => pending.push('\r'),
+                            Some((_, 't')) => pending.push('\t'),
+                            Some((_, '$')) => {
+                                value.push_str(&expand(&pending, &result));
+                                pending.clear();
+                                value.push('$');
+                            }
+                            Some((_, escaped)) => pending.push(escaped),
+                            None => pending.push('\\'),
+                        },
+                        c => pending.push(c),
+                    }
+                }
+
+                match closed_at {
+                    Some(pos) => {
+                        ensure_only_comment(&current[pos + 1..])
+                            .with_context(|| format!("line {}", line_no))?;
+                        break;
+                    }
+                    None => match lines.next() {
+                        Some((_, next_line)) => {
+                            pending.push('\n');
+                            current = next_line.to_string();
+                        }
+                        None => bail!("line {}: unterminated double quote", line_no),
+                    },
+                }
+            }
+
+            value.push_str(&expand(&pending, &result));
+            value
+        } else {
+            let value = match raw_value.find(" #").or_else(|| raw_value.find("\t#")) {
+                Some(comment_start) => &raw_value[..comment_start],
+                None => raw_value,
+            };
+            let value = value.trim_end();
+
+            if value.contains('"') || value.contains('\'') {
+                bail!("line {}: unexpected quote in unquoted value", line_no);
+            }
+
+            expand(value, &result)
+        };
+
+        result.push((key.to_string(), value));
+    }
+
+    Ok(result)
+}
+
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Vec<(String, String)>> {
+    let path = path.as_ref();
+    let content =
+        fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+
+    parse(&content).with_context(|| format!("bad env file {}", path.display()))
+}
+
+/// Load env files, passed with `--env-file` to the subcommand, into the
+/// process environment. This should happen before arguments are parsed for
+/// real, so that `EXG_*` variables from files are picked up as values for
+/// arguments. Spawned commands inherit these variables as well.
+pub fn load_from_matches(matches: &ArgMatches) -> anyhow::Result<()> {
+    let paths = match matches.subcommand() {
+        (_, Some(subcommand)) => subcommand.values_of(ARG_NAME),
+        _ => None,
+    };
+
+    for path in paths.into_iter().flatten() {
+        for (key, value) in load(path)? {
+            if std::env::var_os(&key).is_none() {
+                std::env::set_var(key, value);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    fn vars(content: &str) -> Vec<(String, String)> {
+        parse(content).unwrap()
+    }
+
+    fn var(content: &str) -> String {
+        let mut vars = vars(content);
+        vars.pop().unwrap().1
+    }
+
+    #[test]
+    fn skips_empty_lines_and_comments() {
+        assert_eq!(
+            vars("\n# comment\n  \nA=1\n  # indented comment\nB=2\n"),
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn export_prefix() {
+        assert_eq!(var("export A=1"), "1");
+        assert_eq!(var("export   A = 1"), "1");
+    }
+
+    #[test]
+    fn unquoted_values() {
+        assert_eq!(var("A=  some value  "), "some value");
+        assert_eq!(var("A=value # comment"), "value");
+        assert_eq!(var("A=value\t# comment"), "value");
+        assert_eq!(var("A=value#not-a-comment"), "value#not-a-comment");
+        assert_eq!(var("A="), "");
+    }
+
+    #[test]
+    fn single_quotes_are_literal() {
+        assert_eq!(var(r#"A='$HOME \n "x"'"#), r#"$HOME \n "x""#);
+        assert_eq!(var("A='value' # comment"), "value");
+    }
+
+    #[test]
+    fn double_quotes_escapes() {
+        assert_eq!(var(r#"A="a\nb\tc\rd""#), "a\nb\tc\rd");
+        assert_eq!(var(r#"A="say \"hi\" \\ o/""#), r#"say "hi" \ o/"#);
+        assert_eq!(var(r#"A="price: \$5""#), "price: $5");
+        assert_eq!(var(r#"A="value" # comment"#), "value");
+    }
+
+    #[test]
+    fn double_quotes_multiline() {
+        assert_eq!(
+            vars("A=\"first\nsecond\n  third\"\nB=1"),
+            vec![
+                ("A".to_string(), "first\nsecond\n  third".to_string()),
+                ("B".to_string(), "1".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_variables_defined_earlier() {
+        let content =
+            "HOST=example.com\nPORT=80\nA=$HOST:${PORT}\nB=\"http://${HOST}/\\$PORT\"\nC='$HOST'";
+        let vars = vars(content);
+        assert_eq!(vars[2].1, "example.com:80");
+        assert_eq!(vars[3].1, "http://example.com/$PORT");
+        assert_eq!(vars[4].1, "$HOST");
+    }
+
+    #[test]
+    fn later_definitions_win_in_expansion() {
+        assert_eq!(var("A=1\nA=2\nB=$A"), "2");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("NO_EQUALS_SIGN").is_err());
+        assert!(parse("1A=1").is_err());
+        assert!(parse("A-B=1").is_err());
+        assert!(parse("A='unterminated").is_err());
+        assert!(parse("A=\"unterminated\nstill").is_err());
+        assert!(parse("A='value' trailing").is_err());
+        assert!(parse("A=\"value\" trailing").is_err());
+        assert!(parse("A=it's").is_err());
+    }
+}
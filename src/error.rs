@@ -0,0 +1,152 @@
+//! Errors in the command-line input and the exit codes they map to.
+//!
+//! Exit codes are stable, so that scripts may rely on them:
+//!
+//! | Code  | Meaning                                                      |
+//! |-------|--------------------------------------------------------------|
+//! | 0     | success                                                      |
+//! | 1     | check failed: not formatted, failed test or healthcheck      |
+//! | 64    | usage error: bad argument or environment variable            |
+//! | 69    | network error: cloud is unreachable or client stopped        |
+//! | 71    | system error: e.g. the runtime could not be started          |
+//! | 77    | authentication error: missing or malformed credentials       |
+//! | 78    | configuration error: bad Exofile, env file, Procfile, etc.   |
+//! | 127   | the command could not be spawned                             |
+//! | other | exit code of the spawned command, or 128 + signal number     |
+
+use std::{fmt, str::FromStr, time::Duration};
+
+pub const CHECK_FAILED_EXIT_CODE: i32 = 1;
+pub const USAGE_ERROR_EXIT_CODE: i32 = 64;
+pub const NETWORK_ERROR_EXIT_CODE: i32 = 69;
+pub const OS_ERROR_EXIT_CODE: i32 = 71;
+pub const AUTH_ERROR_EXIT_CODE: i32 = 77;
+pub const CONFIG_ERROR_EXIT_CODE: i32 = 78;
+pub const CHILD_SPAWN_FAILED_EXIT_CODE: i32 = 127;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error("{arg}: {message}")]
+    Usage { arg: String, message: String },
+
+    #[error("{0}")]
+    Auth(String),
+
+    #[error("{0}")]
+    Config(String),
+
+    #[error("{0}")]
+    Network(String),
+
+    #[error("{0}")]
+    Os(String),
+}
+
+impl CliError {
+    /// `arg` names the flag, and the env variable if there is one, e.g.
+    /// `--profile (EXG_PROFILE)`
+    pub fn usage(arg: impl Into<String>, message: impl fmt::Display) -> Self {
+        CliError::Usage {
+            arg: arg.into(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn auth(e: impl fmt::Display) -> Self {
+        CliError::Auth(e.to_string())
+    }
+
+    /// Config errors are usually `anyhow` errors with context, so the whole
+    /// chain is kept
+    pub fn config(e: impl Into<anyhow::Error>) -> Self {
+        CliError::Config(format!("{:#}", e.into()))
+    }
+
+    pub fn network(e: impl fmt::Display) -> Self {
+        CliError::Network(e.to_string())
+    }
+
+    pub fn os(e: impl fmt::Display) -> Self {
+        CliError::Os(e.to_string())
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage { .. } => USAGE_ERROR_EXIT_CODE,
+            CliError::Auth(_) => AUTH_ERROR_EXIT_CODE,
+            CliError::Config(_) => CONFIG_ERROR_EXIT_CODE,
+            CliError::Network(_) => NETWORK_ERROR_EXIT_CODE,
+            CliError::Os(_) => OS_ERROR_EXIT_CODE,
+        }
+    }
+}
+
+pub trait OrExit<T> {
+    /// Print the error and exit with the corresponding exit code
+    fn or_exit(self) -> T;
+}
+
+impl<T> OrExit<T> for Result<T, CliError> {
+    fn or_exit(self) -> T {
+        match self {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+    }
+}
+
+/// Print the error of parsing the command line and exit. Help and version
+/// are printed to stdout and exit with 0, other errors are usage errors
+pub fn exit_on_args_error(e: clap::Error) -> ! {
+    match e.kind {
+        clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => e.exit(),
+        _ => {
+            eprintln!("{}", e.message);
+            std::process::exit(USAGE_ERROR_EXIT_CODE);
+        }
+    }
+}
+
+/// Parse the argument value, if it is set
+pub fn parse_arg<T>(
+    matches: &clap::ArgMatches,
+    name: &str,
+    arg: &str,
+) -> Result<Option<T>, CliError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    matches
+        .value_of(name)
+        .map(|v| {
+            v.parse()
+                .map_err(|e| CliError::usage(arg, format!("bad value `{}`: {}", v, e)))
+        })
+        .transpose()
+}
+
+/// Parse the argument, which is expected to have a default value
+pub fn parse_required_arg<T>(
+    matches: &clap::ArgMatches,
+    name: &str,
+    arg: &str,
+) -> Result<T, CliError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    parse_arg(matches, name, arg)?.ok_or_else(|| CliError::usage(arg, "is not set"))
+}
+
+/// Parse duration in the humantime format, e.g. `10s` or `1m 30s`
+pub fn parse_duration_arg(
+    matches: &clap::ArgMatches,
+    name: &str,
+    arg: &str,
+) -> Result<Duration, CliError> {
+    parse_required_arg::<humantime::Duration>(matches, name, arg).map(Into::into)
+}
@@ -0,0 +1,146 @@
+use anyhow::Context;
+use exogress_common::config_core::ClientConfig;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+/// Lightweight view on Exofile, which contains only the parts used by the
+/// command-line client itself. The view is never parsed from the file
+/// directly: the file is parsed and validated as `ClientConfig`, and the
+/// view is taken from it, see `Exofile::parse`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Exofile {
+    pub version: String,
+    pub revision: u64,
+    pub name: String,
+
+    #[serde(default)]
+    pub mount_points: BTreeMap<String, MountPoint>,
+
+    #[serde(default)]
+    pub upstreams: BTreeMap<String, Upstream>,
+
+    /// Fields, which are not used by the client, e.g. `static-responses`
+    #[serde(flatten)]
+    pub rest: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MountPoint {
+    #[serde(default)]
+    pub handlers: BTreeMap<String, Handler>,
+
+    /// Fields, which are not used by the client, e.g. `rules` or `rescue`
+    #[serde(flatten)]
+    pub rest: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Handler {
+    pub kind: String,
+
+    pub priority: u16,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<Vec<String>>,
+
+    /// Kind-specific fields, e.g. `dir` or `rules`
+    #[serde(flatten)]
+    pub rest: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Upstream {
+    pub port: u16,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<Vec<String>>,
+}
+
+impl Upstream {
+    pub fn host(&self) -> &str {
+        self.host.as_deref().unwrap_or("127.0.0.1")
+    }
+}
+
+/// Items without profiles are always active, others only if the active
+/// profile is listed.
+pub fn is_profile_active(profiles: &Option<Vec<String>>, active_profile: Option<&str>) -> bool {
+    match (profiles, active_profile) {
+        (None, _) => true,
+        (Some(profiles), Some(active_profile)) => profiles.iter().any(|p| p == active_profile),
+        (Some(_), None) => false,
+    }
+}
+
+impl Exofile {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Exofile> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+
+        Exofile::parse(&content).with_context(|| format!("could not parse {}", path.display()))
+    }
+
+    /// Parse the config as `ClientConfig`, so that it is validated by the
+    /// same types as the cloud uses, and take the view from it
+    pub fn parse(content: &str) -> Result<Exofile, serde_yaml::Error> {
+        let config: ClientConfig = serde_yaml::from_str(content)?;
+        serde_yaml::from_value(serde_yaml::to_value(&config)?)
+    }
+
+    /// All profiles mentioned in the config
+    pub fn profiles(&self) -> BTreeSet<&str> {
+        let handlers_profiles = self
+            .mount_points
+            .values()
+            .flat_map(|mount_point| mount_point.handlers.values())
+            .flat_map(|handler| handler.profiles.iter().flatten());
+        let upstreams_profiles = self
+            .upstreams
+            .values()
+            .flat_map(|upstream| upstream.profiles.iter().flatten());
+
+        handlers_profiles
+            .chain(upstreams_profiles)
+            .map(|p| p.as_str())
+            .collect()
+    }
+
+    /// Handlers which are active in the profile, ordered by priority
+    pub fn active_handlers<'a>(
+        &'a self,
+        mount_point: &'a MountPoint,
+        active_profile: Option<&'a str>,
+    ) -> Vec<(&'a String, &'a Handler)> {
+        let mut handlers = mount_point
+            .handlers
+            .iter()
+            .filter(|(_, handler)| is_profile_active(&handler.profiles, active_profile))
+            .collect::<Vec<_>>();
+        handlers.sort_by_key(|(name, handler)| (handler.priority, *name));
+        handlers
+    }
+
+    pub fn active_upstreams<'a>(
+        &'a self,
+        active_profile: Option<&'a str>,
+    ) -> impl Iterator<Item = (&'a String, &'a Upstream)> + 'a {
+        self.upstreams
+            .iter()
+            .filter(move |(_, upstream)| is_profile_active(&upstream.profiles, active_profile))
+    }
+}
@@ -0,0 +1,383 @@
+use crate::{
+    config::add_config_arg,
+    error::{parse_arg, parse_required_arg, CliError, OrExit},
+    exofile::Exofile,
+    gateway::{
+        default_mount_point, facts, render, static_dir_path, AfterInvoke, Outcome,
+        Request as RouteRequest, Route, RouteHandler, Selection, StaticResponse,
+    },
+};
+use anyhow::{anyhow, Context};
+use clap::{App, Arg, ArgMatches};
+use exogress_common::entities::ProfileName;
+use hyper::{
+    body::Bytes,
+    header::{HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE, HOST},
+    service::{make_service_fn, service_fn},
+    Body, Client, Method, Request, Response, StatusCode,
+};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+const SUBCOMMAND: &str = "dev";
+
+/// Headers, which are related to a single connection and are not proxied
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+pub fn dev_app() -> App<'static, 'static> {
+    let app = exogress_common::common_utils::clap::log::add_args(App::new(SUBCOMMAND));
+
+    add_config_arg(app)
+        .about("Serve Exofile locally, without connecting to the cloud")
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .value_name("ADDR:PORT")
+                .help("Address to listen on")
+                .env("EXG_DEV_LISTEN")
+                .default_value("127.0.0.1:8080")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mount_point")
+                .long("mount-point")
+                .value_name("NAME")
+                .help("Mount point to serve [default: `default`, or the only one]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .short("p")
+                .long("profile")
+                .help("Profile name")
+                .env("EXG_PROFILE")
+                .required(false)
+                .takes_value(true),
+        )
+}
+
+struct DevServer {
+    config_path: PathBuf,
+    mount_point: Option<String>,
+    profile: Option<String>,
+    client: Client<hyper::client::HttpConnector>,
+}
+
+/// Request, which is buffered, since it may be passed to several handlers
+struct BufferedRequest {
+    method: Method,
+    uri: hyper::Uri,
+    headers: hyper::HeaderMap,
+    body: Bytes,
+}
+
+fn text_response(status: StatusCode, text: impl Into<String>) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::from(text.into()))
+        .unwrap()
+}
+
+impl DevServer {
+    fn static_response(
+        &self,
+        request: &BufferedRequest,
+        response: &StaticResponse,
+        status_code: Option<u16>,
+    ) -> anyhow::Result<Response<Body>> {
+        let hostname = request
+            .headers
+            .get(HOST)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.split(':').next().unwrap_or(h))
+            .unwrap_or("localhost");
+        let accept = request.headers.get(ACCEPT).and_then(|h| h.to_str().ok());
+
+        let rendered = render(response, status_code, accept, &facts(hostname, "http"))?;
+
+        let mut builder = Response::builder().status(rendered.status_code);
+        for (name, value) in &rendered.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+
+        Ok(builder.body(Body::from(rendered.body))?)
+    }
+
+    async fn serve_static_dir(
+        &self,
+        route_handler: &RouteHandler<'_>,
+        request: &RouteRequest,
+        is_head: bool,
+    ) -> anyhow::Result<Response<Body>> {
+        let config_dir = self.config_path.parent().unwrap_or_else(|| Path::new("."));
+        let path = match static_dir_path(config_dir, route_handler, request)? {
+            Some(path) => path,
+            None => return Ok(text_response(StatusCode::NOT_FOUND, "Not found")),
+        };
+
+        let content = match tokio::fs::read(&path).await {
+            Ok(content) => content,
+            Err(_) => return Ok(text_response(StatusCode::NOT_FOUND, "Not found")),
+        };
+
+        let mime = mime_guess::from_path(&path).first_or_octet_stream();
+        let response = Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, mime.as_ref())
+            .header(hyper::header::CONTENT_LENGTH, content.len());
+
+        let body = if is_head {
+            Body::empty()
+        } else {
+            Body::from(content)
+        };
+
+        Ok(response.body(body)?)
+    }
+
+    async fn proxy(
+        &self,
+        exofile: &Exofile,
+        route_handler: &RouteHandler<'_>,
+        request: &BufferedRequest,
+    ) -> anyhow::Result<Response<Body>> {
+        let upstream_name = route_handler
+            .handler
+            .upstream
+            .as_ref()
+            .ok_or_else(|| anyhow!("handler `{}` has no upstream", route_handler.name))?;
+        let upstream = exofile
+            .upstreams
+            .get(upstream_name)
+            .ok_or_else(|| anyhow!("upstream `{}` is not defined", upstream_name))?;
+
+        let path_and_query = request
+            .uri
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/");
+        let uri: hyper::Uri = format!(
+            "http://{}:{}{}",
+            upstream.host(),
+            upstream.port,
+            path_and_query
+        )
+        .parse()?;
+
+        let mut proxy_request = Request::builder()
+            .method(request.method.clone())
+            .uri(uri)
+            .body(Body::from(request.body.clone()))?;
+
+        for (name, value) in &request.headers {
+            if !HOP_BY_HOP_HEADERS.contains(&name.as_str()) {
+                proxy_request.headers_mut().append(name, value.clone());
+            }
+        }
+        if let Some(host) = request.headers.get(HOST) {
+            proxy_request
+                .headers_mut()
+                .insert(HeaderName::from_static("x-forwarded-host"), host.clone());
+        }
+        proxy_request.headers_mut().insert(
+            HeaderName::from_static("x-forwarded-proto"),
+            HeaderValue::from_static("http"),
+        );
+
+        let mut response = self
+            .client
+            .request(proxy_request)
+            .await
+            .with_context(|| format!("upstream `{}` is not reachable", upstream_name))?;
+
+        for name in HOP_BY_HOP_HEADERS {
+            response.headers_mut().remove(*name);
+        }
+
+        Ok(response)
+    }
+
+    async fn invoke(
+        &self,
+        exofile: &Exofile,
+        route_handler: &RouteHandler<'_>,
+        route_request: &RouteRequest,
+        request: &BufferedRequest,
+    ) -> Response<Body> {
+        let result = match route_handler.handler.kind.as_str() {
+            "static-dir" => {
+                self.serve_static_dir(route_handler, route_request, request.method == Method::HEAD)
+                    .await
+            }
+            "proxy" => self.proxy(exofile, route_handler, request).await,
+            kind => {
+                return text_response(
+                    StatusCode::NOT_IMPLEMENTED,
+                    format!("handler kind `{}` is not supported by `exogress dev`", kind),
+                )
+            }
+        };
+
+        result.unwrap_or_else(|e| {
+            warn!("handler `{}` failed: {:#}", route_handler.name, e);
+            text_response(StatusCode::BAD_GATEWAY, format!("{:#}", e))
+        })
+    }
+
+    async fn handle(&self, request: BufferedRequest) -> anyhow::Result<Response<Body>> {
+        let exofile = Exofile::load(&self.config_path)?;
+        let mount_point = match &self.mount_point {
+            Some(mount_point) => mount_point.as_str(),
+            None => default_mount_point(&exofile)?,
+        };
+        let route = Route::new(&exofile, mount_point, self.profile.as_deref())?;
+
+        let route_request = RouteRequest::new(request.method.as_str(), request.uri.path());
+        let mut trace = vec![];
+        let mut start = 0;
+
+        let response = loop {
+            match route.select(&route_request, start, &mut trace) {
+                Selection::Invoke { handler, rule } => {
+                    let route_handler = &route.handlers[handler];
+                    let response = self
+                        .invoke(&exofile, route_handler, &route_request, &request)
+                        .await;
+                    let outcome = Outcome::StatusCode(response.status().as_u16());
+
+                    match route.after_invoke(handler, rule, &outcome, &mut trace) {
+                        AfterInvoke::Done => break response,
+                        AfterInvoke::NextHandler => start = handler + 1,
+                        AfterInvoke::Respond {
+                            response,
+                            status_code,
+                        } => break self.static_response(&request, response, status_code)?,
+                        AfterInvoke::Exception(e) => {
+                            break text_response(StatusCode::INTERNAL_SERVER_ERROR, e)
+                        }
+                    }
+                }
+                Selection::Respond {
+                    response,
+                    status_code,
+                    ..
+                } => break self.static_response(&request, response, status_code)?,
+                Selection::Exception(e) => {
+                    break text_response(StatusCode::INTERNAL_SERVER_ERROR, e)
+                }
+                Selection::NotFound => break text_response(StatusCode::NOT_FOUND, "Not found"),
+            }
+        };
+
+        info!(
+            "{} -> {} ({})",
+            route_request,
+            response.status(),
+            trace.join("; ")
+        );
+
+        Ok(response)
+    }
+}
+
+async fn serve(addr: SocketAddr, server: Arc<DevServer>) -> anyhow::Result<()> {
+    let make_svc = make_service_fn(move |_conn| {
+        let server = server.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let server = server.clone();
+                async move {
+                    let (parts, body) = req.into_parts();
+                    let response = match hyper::body::to_bytes(body).await {
+                        Ok(body) => {
+                            let request = BufferedRequest {
+                                method: parts.method,
+                                uri: parts.uri,
+                                headers: parts.headers,
+                                body,
+                            };
+                            server.handle(request).await.unwrap_or_else(|e| {
+                                error!("{:#}", e);
+                                text_response(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e))
+                            })
+                        }
+                        Err(e) => text_response(StatusCode::BAD_REQUEST, e.to_string()),
+                    };
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+
+    let server = hyper::Server::try_bind(&addr)
+        .with_context(|| format!("could not listen on {}", addr))?
+        .serve(make_svc);
+    info!("serving on http://{}", addr);
+
+    server
+        .with_graceful_shutdown(async {
+            tokio::signal::ctrl_c().await.ok();
+        })
+        .await?;
+
+    Ok(())
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    if let Some(app) = args.subcommand_matches(SUBCOMMAND) {
+        exogress_common::common_utils::clap::log::handle(app, "exogress");
+
+        let config_path = PathBuf::from(app.value_of("config").unwrap());
+        let listen: SocketAddr =
+            parse_required_arg(app, "listen", "--listen (EXG_DEV_LISTEN)").or_exit();
+        // validated the same way as in `spawn`
+        let _: Option<ProfileName> = parse_arg(app, "profile", "--profile (EXG_PROFILE)").or_exit();
+
+        // check the config upfront, it is reloaded on every request later
+        let exofile = Exofile::load(&config_path)
+            .map_err(CliError::config)
+            .or_exit();
+        let mount_point = app.value_of("mount_point").map(|m| m.to_string());
+        let mount_point_name = match &mount_point {
+            Some(mount_point) => mount_point.as_str(),
+            None => default_mount_point(&exofile)
+                .map_err(CliError::config)
+                .or_exit(),
+        };
+        Route::new(&exofile, mount_point_name, app.value_of("profile"))
+            .map_err(CliError::config)
+            .or_exit();
+
+        let server = Arc::new(DevServer {
+            config_path,
+            mount_point,
+            profile: app.value_of("profile").map(|p| p.to_string()),
+            client: Client::new(),
+        });
+
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(serve(listen, server))
+            .map_err(CliError::network)
+            .or_exit();
+
+        std::process::exit(0);
+    }
+}
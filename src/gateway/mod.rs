@@ -0,0 +1,1059 @@
+//! Local emulation of the Exogress gateway: rules of handlers, their actions
+//! and rescue chains. Used by `exogress dev` to serve requests and by
+//! `exogress config test` to explain how requests are routed.
+//!
+//! Routing is split into steps, so that the caller invokes handlers itself:
+//! `Route::select` finds the handler to invoke or the response to send, and
+//! after the handler responds, `Route::after_invoke` decides whether the
+//! response is rescued.
+//!
+//! Rules are read from `Exofile`, which is taken from the config parsed and
+//! validated as `ClientConfig`, so the types below only describe the canonical
+//! form produced by `ClientConfig` and never see the raw file.
+
+use crate::exofile::{Exofile, Handler, MountPoint};
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+pub mod dev;
+
+/// Catch-all rule, which is applied to handlers without rules or with an
+/// empty list of rules
+const DEFAULT_RULE: &str = r#"{"filter": {"path": ["*"]}, "action": "invoke"}"#;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum PathSegment {
+    Single(String),
+    AnyOf(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrailingSlash {
+    Allow,
+    Require,
+    Deny,
+}
+
+impl Default for TrailingSlash {
+    fn default() -> Self {
+        TrailingSlash::Allow
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Filter {
+    pub path: Vec<PathSegment>,
+
+    #[serde(default)]
+    pub methods: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub trailing_slash: TrailingSlash,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResponseEngine {
+    Handlebars,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ResponseBody {
+    pub content_type: String,
+    pub content: String,
+
+    #[serde(default)]
+    pub engine: Option<ResponseEngine>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RedirectType {
+    MultipleChoices,
+    MovedPermanently,
+    Found,
+    SeeOther,
+    NotModified,
+    TemporaryRedirect,
+    PermanentRedirect,
+}
+
+impl RedirectType {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            RedirectType::MultipleChoices => 300,
+            RedirectType::MovedPermanently => 301,
+            RedirectType::Found => 302,
+            RedirectType::SeeOther => 303,
+            RedirectType::NotModified => 304,
+            RedirectType::TemporaryRedirect => 307,
+            RedirectType::PermanentRedirect => 308,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum StaticResponse {
+    #[serde(rename_all = "kebab-case")]
+    Raw {
+        #[serde(default)]
+        status_code: Option<u16>,
+
+        #[serde(default)]
+        fallback_accept: Option<String>,
+
+        #[serde(default)]
+        body: Vec<ResponseBody>,
+
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    #[serde(rename_all = "kebab-case")]
+    Redirect {
+        redirect_type: RedirectType,
+        destination: String,
+
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+}
+
+/// Static response is either defined in place, or refers to
+/// `static-responses` by name
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum StaticResponseRef {
+    Name(String),
+    Inline(StaticResponse),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum Action {
+    Invoke {
+        #[serde(default)]
+        rescue: Vec<Rescue>,
+    },
+    NextHandler,
+    #[serde(rename_all = "kebab-case")]
+    Respond {
+        static_response: StaticResponseRef,
+
+        #[serde(default)]
+        status_code: Option<u16>,
+    },
+    Throw {
+        exception: String,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum RescueAction {
+    NextHandler,
+    #[serde(rename_all = "kebab-case")]
+    Respond {
+        static_response: StaticResponseRef,
+
+        #[serde(default)]
+        status_code: Option<u16>,
+    },
+    Throw {
+        exception: String,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rescue {
+    pub catch: String,
+
+    #[serde(flatten)]
+    pub action: RescueAction,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rule {
+    pub filter: Filter,
+
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// Request as seen by rules
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub segments: Vec<String>,
+    pub has_trailing_slash: bool,
+}
+
+impl Request {
+    pub fn new(method: &str, path: &str) -> Self {
+        let path = path.split('?').next().unwrap_or("");
+        let segments = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                percent_encoding::percent_decode_str(s)
+                    .decode_utf8_lossy()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+
+        Request {
+            method: method.to_uppercase(),
+            has_trailing_slash: !segments.is_empty() && path.ends_with('/'),
+            segments,
+        }
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} /{}", self.method, self.segments.join("/"))?;
+        if self.has_trailing_slash {
+            write!(f, "/")?;
+        }
+        Ok(())
+    }
+}
+
+/// `*` matches any number of segments, `?` matches exactly one
+fn segments_match(pattern: &[PathSegment], segments: &[String]) -> bool {
+    match pattern.split_first() {
+        None => segments.is_empty(),
+        Some((PathSegment::Single(s), rest)) if s == "*" => {
+            (0..=segments.len()).any(|skip| segments_match(rest, &segments[skip..]))
+        }
+        Some((segment, rest)) => {
+            let (first, other_segments) = match segments.split_first() {
+                Some(r) => r,
+                None => return false,
+            };
+
+            let is_match = match segment {
+                PathSegment::Single(s) => s == "?" || s == first,
+                PathSegment::AnyOf(variants) => variants.iter().any(|v| v == first),
+            };
+
+            is_match && segments_match(rest, other_segments)
+        }
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, request: &Request) -> bool {
+        let is_method_allowed = match &self.methods {
+            None => true,
+            Some(methods) => methods
+                .iter()
+                .any(|m| m == "*" || m.eq_ignore_ascii_case(&request.method)),
+        };
+
+        let is_trailing_slash_allowed = match self.trailing_slash {
+            TrailingSlash::Allow => true,
+            TrailingSlash::Require => request.has_trailing_slash,
+            TrailingSlash::Deny => !request.has_trailing_slash,
+        };
+
+        is_method_allowed
+            && is_trailing_slash_allowed
+            && segments_match(&self.path, &request.segments)
+    }
+}
+
+/// `catch` in the form of `status-code:404` or `status-code:5xx`, or
+/// `exception:NAME`
+fn is_caught(catch: &str, outcome: &Outcome) -> bool {
+    match outcome {
+        Outcome::StatusCode(status_code) => match catch.strip_prefix("status-code:") {
+            Some(pattern) => {
+                let status_code = status_code.to_string();
+                pattern.len() == status_code.len()
+                    && pattern
+                        .chars()
+                        .zip(status_code.chars())
+                        .all(|(p, c)| p == 'x' || p == 'X' || p == c)
+            }
+            None => false,
+        },
+        Outcome::Exception(exception) => catch.strip_prefix("exception:") == Some(exception),
+    }
+}
+
+/// Result of the handler, which may be rescued
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    StatusCode(u16),
+    Exception(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::StatusCode(status_code) => write!(f, "status-code:{}", status_code),
+            Outcome::Exception(exception) => write!(f, "exception:{}", exception),
+        }
+    }
+}
+
+fn parse_field<T: serde::de::DeserializeOwned>(
+    rest: &BTreeMap<String, serde_yaml::Value>,
+    field: &str,
+) -> anyhow::Result<Option<T>> {
+    rest.get(field)
+        .map(|value| serde_yaml::from_value(value.clone()))
+        .transpose()
+        .with_context(|| format!("bad {}", field))
+}
+
+#[derive(Debug)]
+pub struct RouteHandler<'a> {
+    pub name: &'a str,
+    pub handler: &'a Handler,
+    pub rules: Vec<Rule>,
+    /// Whether `rules` is the default rule
+    pub is_default_rule: bool,
+    pub rescue: Vec<Rescue>,
+    pub static_responses: BTreeMap<String, StaticResponse>,
+}
+
+/// Handlers of the mount point, which are active in the profile, ordered
+/// by priority
+#[derive(Debug)]
+pub struct Route<'a> {
+    pub mount_point_name: &'a str,
+    pub handlers: Vec<RouteHandler<'a>>,
+    rescue: Vec<Rescue>,
+    /// Static responses of the mount point and the whole config
+    static_responses: BTreeMap<String, StaticResponse>,
+}
+
+#[derive(Debug)]
+pub enum Selection<'a> {
+    Invoke {
+        handler: usize,
+        rule: Option<usize>,
+    },
+    Respond {
+        /// Handler, which responded by its rule or rescue, `None` if the
+        /// mount point responded
+        handler: Option<usize>,
+        response: &'a StaticResponse,
+        status_code: Option<u16>,
+    },
+    Exception(String),
+    /// No handler processed the request
+    NotFound,
+}
+
+#[derive(Debug)]
+pub enum AfterInvoke<'a> {
+    /// Response of the handler is sent as is
+    Done,
+    NextHandler,
+    Respond {
+        response: &'a StaticResponse,
+        status_code: Option<u16>,
+    },
+    Exception(String),
+}
+
+impl<'a> Route<'a> {
+    pub fn new(
+        exofile: &'a Exofile,
+        mount_point_name: &'a str,
+        profile: Option<&'a str>,
+    ) -> anyhow::Result<Self> {
+        let mount_point: &MountPoint = exofile
+            .mount_points
+            .get(mount_point_name)
+            .ok_or_else(|| anyhow!("mount point `{}` is not found", mount_point_name))?;
+
+        let mut static_responses: BTreeMap<String, StaticResponse> =
+            parse_field(&exofile.rest, "static-responses")?.unwrap_or_default();
+        static_responses.extend(
+            parse_field::<BTreeMap<String, StaticResponse>>(&mount_point.rest, "static-responses")?
+                .unwrap_or_default(),
+        );
+
+        let default_rule: Rule = serde_json::from_str(DEFAULT_RULE).unwrap();
+
+        let handlers = exofile
+            .active_handlers(mount_point, profile)
+            .into_iter()
+            .map(|(name, handler)| {
+                let rules = parse_field::<Vec<Rule>>(&handler.rest, "rules")?
+                    .filter(|rules| !rules.is_empty());
+                let is_default_rule = rules.is_none();
+
+                Ok(RouteHandler {
+                    name,
+                    handler,
+                    rules: rules.unwrap_or_else(|| vec![default_rule.clone()]),
+                    is_default_rule,
+                    rescue: parse_field(&handler.rest, "rescue")?.unwrap_or_default(),
+                    static_responses: parse_field(&handler.rest, "static-responses")?
+                        .unwrap_or_default(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+            .with_context(|| format!("bad handler in mount point `{}`", mount_point_name))?;
+
+        Ok(Route {
+            mount_point_name,
+            handlers,
+            rescue: parse_field(&mount_point.rest, "rescue")?.unwrap_or_default(),
+            static_responses,
+        })
+    }
+
+    fn static_response(
+        &'a self,
+        handler: Option<usize>,
+        response: &'a StaticResponseRef,
+    ) -> Result<&'a StaticResponse, String> {
+        match response {
+            StaticResponseRef::Inline(response) => Ok(response),
+            StaticResponseRef::Name(name) => handler
+                .and_then(|idx| self.handlers[idx].static_responses.get(name))
+                .or_else(|| self.static_responses.get(name))
+                .ok_or_else(|| format!("static response `{}` is not defined", name)),
+        }
+    }
+
+    /// Find the first handler starting from `start`, which processes the
+    /// request. Steps are reported to the trace.
+    pub fn select(
+        &'a self,
+        request: &Request,
+        start: usize,
+        trace: &mut Vec<String>,
+    ) -> Selection<'a> {
+        for (idx, route_handler) in self.handlers.iter().enumerate().skip(start) {
+            let matched = route_handler
+                .rules
+                .iter()
+                .enumerate()
+                .find(|(_, rule)| rule.filter.matches(request));
+
+            let (rule_idx, rule) = match matched {
+                Some(r) => r,
+                None => {
+                    trace.push(format!(
+                        "handler `{}`: no rule matched, skip",
+                        route_handler.name
+                    ));
+                    continue;
+                }
+            };
+
+            let rule_name = if route_handler.is_default_rule {
+                "default rule".to_string()
+            } else {
+                format!("rule #{}", rule_idx + 1)
+            };
+
+            match &rule.action {
+                Action::Invoke { .. } => {
+                    trace.push(format!(
+                        "handler `{}` ({}): {} matched, action invoke",
+                        route_handler.name, route_handler.handler.kind, rule_name
+                    ));
+                    return Selection::Invoke {
+                        handler: idx,
+                        rule: Some(rule_idx),
+                    };
+                }
+                Action::NextHandler => {
+                    trace.push(format!(
+                        "handler `{}`: {} matched, action next-handler",
+                        route_handler.name, rule_name
+                    ));
+                }
+                Action::Respond {
+                    static_response,
+                    status_code,
+                } => {
+                    trace.push(format!(
+                        "handler `{}`: {} matched, action respond",
+                        route_handler.name, rule_name
+                    ));
+                    return match self.static_response(Some(idx), static_response) {
+                        Ok(response) => Selection::Respond {
+                            handler: Some(idx),
+                            response,
+                            status_code: *status_code,
+                        },
+                        Err(e) => Selection::Exception(e),
+                    };
+                }
+                Action::Throw { exception } => {
+                    trace.push(format!(
+                        "handler `{}`: {} matched, action throw `{}`",
+                        route_handler.name, rule_name, exception
+                    ));
+                    return self.rescue_exception(Some(idx), exception, trace);
+                }
+            }
+        }
+
+        trace.push(format!(
+            "mount point `{}`: no handler left",
+            self.mount_point_name
+        ));
+        Selection::NotFound
+    }
+
+    /// Exceptions thrown by rules may be caught by the rescue of the handler
+    /// or the mount point, but only responding is allowed there
+    fn rescue_exception(
+        &'a self,
+        handler: Option<usize>,
+        exception: &str,
+        trace: &mut Vec<String>,
+    ) -> Selection<'a> {
+        match self.after_outcome(
+            handler,
+            None,
+            &Outcome::Exception(exception.to_string()),
+            trace,
+        ) {
+            AfterInvoke::Respond {
+                response,
+                status_code,
+            } => Selection::Respond {
+                handler,
+                response,
+                status_code,
+            },
+            AfterInvoke::Exception(e) => Selection::Exception(e),
+            AfterInvoke::Done | AfterInvoke::NextHandler => {
+                Selection::Exception(exception.to_string())
+            }
+        }
+    }
+
+    /// Decide what to do with the result of the invoked handler. Rescue of
+    /// the rule is checked first, then of the handler, then of the mount
+    /// point.
+    pub fn after_invoke(
+        &'a self,
+        handler: usize,
+        rule: Option<usize>,
+        outcome: &Outcome,
+        trace: &mut Vec<String>,
+    ) -> AfterInvoke<'a> {
+        trace.push(format!(
+            "handler `{}` responded with {}",
+            self.handlers[handler].name, outcome
+        ));
+        self.after_outcome(Some(handler), rule, outcome, trace)
+    }
+
+    fn after_outcome(
+        &'a self,
+        handler: Option<usize>,
+        rule: Option<usize>,
+        outcome: &Outcome,
+        trace: &mut Vec<String>,
+    ) -> AfterInvoke<'a> {
+        let rule_rescue = handler
+            .zip(rule)
+            .and_then(|(h, r)| match &self.handlers[h].rules[r].action {
+                Action::Invoke { rescue } => Some(rescue),
+                _ => None,
+            })
+            .into_iter()
+            .flatten()
+            .map(|rescue| ("rule", rescue));
+        let handler_rescue = handler
+            .map(|h| &self.handlers[h].rescue)
+            .into_iter()
+            .flatten()
+            .map(|rescue| ("handler", rescue));
+        let mount_point_rescue = self.rescue.iter().map(|rescue| ("mount point", rescue));
+
+        let caught = rule_rescue
+            .chain(handler_rescue)
+            .chain(mount_point_rescue)
+            .find(|(_, rescue)| is_caught(&rescue.catch, outcome));
+
+        let (level, rescue) = match caught {
+            Some(r) => r,
+            None => {
+                return match outcome {
+                    Outcome::StatusCode(_) => AfterInvoke::Done,
+                    Outcome::Exception(e) => AfterInvoke::Exception(e.clone()),
+                }
+            }
+        };
+
+        match &rescue.action {
+            RescueAction::NextHandler => {
+                trace.push(format!(
+                    "{} rescue `{}` caught, action next-handler",
+                    level, rescue.catch
+                ));
+                AfterInvoke::NextHandler
+            }
+            RescueAction::Respond {
+                static_response,
+                status_code,
+            } => {
+                trace.push(format!(
+                    "{} rescue `{}` caught, action respond",
+                    level, rescue.catch
+                ));
+                match self.static_response(handler, static_response) {
+                    Ok(response) => AfterInvoke::Respond {
+                        response,
+                        status_code: *status_code,
+                    },
+                    Err(e) => AfterInvoke::Exception(e),
+                }
+            }
+            RescueAction::Throw { exception } => {
+                trace.push(format!(
+                    "{} rescue `{}` caught, action throw `{}`",
+                    level, rescue.catch, exception
+                ));
+                AfterInvoke::Exception(exception.clone())
+            }
+        }
+    }
+}
+
+/// Facts, which are available in templates of static responses as
+/// `this.facts`
+pub fn facts(hostname: &str, scheme: &str) -> serde_json::Value {
+    serde_json::json!({
+        "mount_point_hostname": hostname,
+        "mount_point_base_url": format!("{}://{}", scheme, hostname),
+    })
+}
+
+/// Static response rendered for the request
+#[derive(Debug)]
+pub struct RenderedResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+fn is_accepted(accept: &str, content_type: &str) -> bool {
+    let (kind, subkind) = content_type.split_once('/').unwrap_or((content_type, ""));
+
+    accept
+        .split(',')
+        .map(|item| item.split(';').next().unwrap_or("").trim())
+        .any(|item| {
+            item == "*/*"
+                || item.eq_ignore_ascii_case(content_type)
+                || (item.ends_with("/*")
+                    && item.trim_end_matches("/*") == kind
+                    && !subkind.is_empty())
+        })
+}
+
+/// Render the static response. Body is selected by the `Accept` header,
+/// falling back to `fallback-accept`.
+pub fn render(
+    response: &StaticResponse,
+    status_code: Option<u16>,
+    accept: Option<&str>,
+    facts: &serde_json::Value,
+) -> anyhow::Result<RenderedResponse> {
+    match response {
+        StaticResponse::Redirect {
+            redirect_type,
+            destination,
+            headers,
+        } => {
+            let mut headers = headers
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect::<Vec<_>>();
+            headers.push(("location".to_string(), destination.clone()));
+
+            Ok(RenderedResponse {
+                status_code: status_code.unwrap_or_else(|| redirect_type.status_code()),
+                headers,
+                body: String::new(),
+            })
+        }
+        StaticResponse::Raw {
+            status_code: response_status_code,
+            fallback_accept,
+            body,
+            headers,
+        } => {
+            let selected = accept
+                .and_then(|accept| body.iter().find(|b| is_accepted(accept, &b.content_type)))
+                .or_else(|| {
+                    fallback_accept
+                        .as_ref()
+                        .and_then(|fallback| body.iter().find(|b| &b.content_type == fallback))
+                })
+                .or_else(|| body.first());
+
+            let mut headers = headers
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect::<Vec<_>>();
+
+            let content = match selected {
+                Some(selected) => {
+                    headers.push(("content-type".to_string(), selected.content_type.clone()));
+                    match selected.engine {
+                        Some(ResponseEngine::Handlebars) => handlebars::Handlebars::new()
+                            .render_template(
+                                &selected.content,
+                                &serde_json::json!({ "facts": facts }),
+                            )
+                            .context("could not render static response")?,
+                        None => selected.content.clone(),
+                    }
+                }
+                None => String::new(),
+            };
+
+            Ok(RenderedResponse {
+                status_code: status_code.or(*response_status_code).unwrap_or(200),
+                headers,
+                body: content,
+            })
+        }
+    }
+}
+
+/// Segment is a single file name, so that joining it can't leave the
+/// directory, e.g. not `..`, `a/b`, or `C:` on Windows
+fn is_file_name(segment: &str) -> bool {
+    let mut components = Path::new(segment).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+/// File, which the `static-dir` handler serves for the request, or `None`
+/// if there is no such file. The directory is relative to the config.
+pub fn static_dir_path(
+    config_dir: &Path,
+    route_handler: &RouteHandler<'_>,
+    request: &Request,
+) -> anyhow::Result<Option<PathBuf>> {
+    let dir = route_handler
+        .handler
+        .rest
+        .get("dir")
+        .and_then(|dir| dir.as_str())
+        .ok_or_else(|| anyhow!("handler `{}` has no dir", route_handler.name))?;
+
+    if !request.segments.iter().all(|s| is_file_name(s)) {
+        return Ok(None);
+    }
+
+    let mut path = config_dir.join(dir);
+    path.extend(&request.segments);
+
+    if path.is_dir() {
+        path.push("index.html");
+    }
+
+    Ok(if path.is_file() { Some(path) } else { None })
+}
+
+/// Mount point to use, if it is not set explicitly: `default`, or the only
+/// one in the config
+pub fn default_mount_point(exofile: &Exofile) -> anyhow::Result<&str> {
+    if exofile.mount_points.contains_key("default") {
+        return Ok("default");
+    }
+
+    let mut names = exofile.mount_points.keys();
+    match (names.next(), names.next()) {
+        (Some(name), None) => Ok(name),
+        (None, _) => bail!("no mount points in the config"),
+        (Some(_), Some(_)) => bail!("there are multiple mount points, please select one"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(segments: &[&str]) -> Vec<PathSegment> {
+        segments
+            .iter()
+            .map(|s| PathSegment::Single(s.to_string()))
+            .collect()
+    }
+
+    fn segments(path: &str) -> Vec<String> {
+        Request::new("GET", path).segments
+    }
+
+    #[test]
+    fn request() {
+        let request = Request::new("get", "/a/b%20c/?q=/d/");
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.segments, vec!["a", "b c"]);
+        assert!(request.has_trailing_slash);
+
+        assert!(!Request::new("GET", "/").has_trailing_slash);
+        assert!(!Request::new("GET", "/a?q=/").has_trailing_slash);
+    }
+
+    #[test]
+    fn segments_match_table() {
+        let cases: &[(&[&str], &str, bool)] = &[
+            (&[], "/", true),
+            (&[], "/a", false),
+            (&["*"], "/", true),
+            (&["*"], "/a/b", true),
+            (&["a"], "/a", true),
+            (&["a"], "/b", false),
+            (&["a"], "/a/b", false),
+            (&["?"], "/a", true),
+            (&["?"], "/", false),
+            (&["?"], "/a/b", false),
+            (&["a", "*"], "/a", true),
+            (&["a", "*"], "/a/b/c", true),
+            (&["a", "*"], "/b/c", false),
+            (&["*", "c"], "/a/b/c", true),
+            (&["*", "c"], "/a/b", false),
+            (&["a", "?", "c"], "/a/b/c", true),
+            (&["a", "?", "c"], "/a/c", false),
+            (&["*", "b", "*"], "/a/b/c", true),
+            (&["*", "b", "*"], "/a/c", false),
+            (&["a b"], "/a%20b", true),
+        ];
+
+        for (p, path, expected) in cases {
+            assert_eq!(
+                segments_match(&pattern(p), &segments(path)),
+                *expected,
+                "{:?} on {}",
+                p,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn any_of_segment() {
+        let p = vec![
+            PathSegment::AnyOf(vec!["a".to_string(), "b".to_string()]),
+            PathSegment::Single("*".to_string()),
+        ];
+
+        assert!(segments_match(&p, &segments("/a/x")));
+        assert!(segments_match(&p, &segments("/b")));
+        assert!(!segments_match(&p, &segments("/c/x")));
+        assert!(!segments_match(&p, &segments("/")));
+    }
+
+    #[test]
+    fn is_caught_table() {
+        let cases = [
+            ("status-code:404", Outcome::StatusCode(404), true),
+            ("status-code:404", Outcome::StatusCode(405), false),
+            ("status-code:4xx", Outcome::StatusCode(404), true),
+            ("status-code:4XX", Outcome::StatusCode(418), true),
+            ("status-code:4xx", Outcome::StatusCode(500), false),
+            ("status-code:5x", Outcome::StatusCode(500), false),
+            ("status-code:5xxx", Outcome::StatusCode(500), false),
+            ("status-code:xxx", Outcome::StatusCode(200), true),
+            ("exception:404", Outcome::StatusCode(404), false),
+            (
+                "exception:proxy-error",
+                Outcome::Exception("proxy-error".to_string()),
+                true,
+            ),
+            (
+                "exception:proxy-error",
+                Outcome::Exception("other".to_string()),
+                false,
+            ),
+            (
+                "status-code:404",
+                Outcome::Exception("404".to_string()),
+                false,
+            ),
+        ];
+
+        for (catch, outcome, expected) in &cases {
+            assert_eq!(
+                is_caught(catch, outcome),
+                *expected,
+                "{} on {}",
+                catch,
+                outcome
+            );
+        }
+    }
+
+    fn filter(methods: Option<&[&str]>, trailing_slash: TrailingSlash) -> Filter {
+        Filter {
+            path: pattern(&["*"]),
+            methods: methods.map(|m| m.iter().map(|m| m.to_string()).collect()),
+            trailing_slash,
+        }
+    }
+
+    #[test]
+    fn filter_methods() {
+        let cases: &[(Option<&[&str]>, &str, bool)] = &[
+            (None, "GET", true),
+            (None, "DELETE", true),
+            (Some(&["GET"]), "GET", true),
+            (Some(&["get"]), "GET", true),
+            (Some(&["GET"]), "post", false),
+            (Some(&["GET", "POST"]), "post", true),
+            (Some(&["*"]), "PATCH", true),
+            (Some(&[]), "GET", false),
+        ];
+
+        for (methods, method, expected) in cases {
+            assert_eq!(
+                filter(*methods, TrailingSlash::Allow).matches(&Request::new(method, "/a")),
+                *expected,
+                "{:?} on {}",
+                methods,
+                method
+            );
+        }
+    }
+
+    #[test]
+    fn filter_trailing_slash() {
+        let cases = [
+            (TrailingSlash::Allow, "/a", true),
+            (TrailingSlash::Allow, "/a/", true),
+            (TrailingSlash::Require, "/a/", true),
+            (TrailingSlash::Require, "/a", false),
+            (TrailingSlash::Require, "/", false),
+            (TrailingSlash::Deny, "/a", true),
+            (TrailingSlash::Deny, "/a/", false),
+            (TrailingSlash::Deny, "/", true),
+        ];
+
+        for (trailing_slash, path, expected) in &cases {
+            assert_eq!(
+                filter(None, *trailing_slash).matches(&Request::new("GET", path)),
+                *expected,
+                "{:?} on {}",
+                trailing_slash,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn filter_path() {
+        let filter = Filter {
+            path: pattern(&["api", "?"]),
+            methods: None,
+            trailing_slash: TrailingSlash::Allow,
+        };
+
+        assert!(filter.matches(&Request::new("GET", "/api/users")));
+        assert!(!filter.matches(&Request::new("GET", "/api")));
+        assert!(!filter.matches(&Request::new("GET", "/api/users/1")));
+    }
+
+    #[test]
+    fn file_names() {
+        assert!(is_file_name("index.html"));
+        assert!(is_file_name(".well-known"));
+        assert!(!is_file_name(""));
+        assert!(!is_file_name("."));
+        assert!(!is_file_name(".."));
+        assert!(!is_file_name("a/b"));
+        assert!(!is_file_name("/etc"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_file_names() {
+        assert!(!is_file_name("C:"));
+        assert!(!is_file_name(r"a\b"));
+        assert!(!is_file_name(r"\\?\C:\"));
+    }
+
+    #[test]
+    fn templates_are_routed() {
+        let templates = [
+            (
+                "laravel-artisan",
+                include_str!("../../platforms_templates/laravel-artisan/Exofile.yml").to_string(),
+                "/",
+            ),
+            (
+                "proxy",
+                include_str!("../../platforms_templates/proxy/Exofile.yml.handlebars")
+                    .replace("{{ this.port }}", "3000"),
+                "/",
+            ),
+            (
+                "rails",
+                include_str!("../../platforms_templates/rails/Exofile.yml").to_string(),
+                "/",
+            ),
+            (
+                "svelte",
+                include_str!("../../platforms_templates/svelte/Exofile.yml").to_string(),
+                "/",
+            ),
+            (
+                "synapse-admin",
+                include_str!("../../platforms_templates/synapse/synapse-admin/Exofile.yml")
+                    .to_string(),
+                "/",
+            ),
+            (
+                "synapse-server",
+                include_str!("../../platforms_templates/synapse/synapse-server/Exofile.yml")
+                    .to_string(),
+                "/_matrix/client/versions",
+            ),
+        ];
+
+        for (name, content, path) in &templates {
+            let exofile = Exofile::parse(content).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            let profiles = exofile.profiles();
+            // handlers of the svelte template are active in profiles only
+            let profiles = if profiles.is_empty() {
+                vec![None]
+            } else {
+                profiles.into_iter().map(Some).collect()
+            };
+
+            for profile in profiles {
+                let route = Route::new(&exofile, "default", profile)
+                    .unwrap_or_else(|e| panic!("{}: {:#}", name, e));
+                let mut trace = vec![];
+
+                match route.select(&Request::new("GET", path), 0, &mut trace) {
+                    Selection::Invoke { .. } => {}
+                    selection => panic!(
+                        "{} with profile {:?}: {:?}, {:?}",
+                        name, profile, selection, trace
+                    ),
+                }
+            }
+        }
+    }
+}
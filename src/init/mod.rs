@@ -9,6 +9,7 @@ pub mod matrix_synapse;
 pub mod proxy;
 pub mod rails;
 pub mod svelte;
+pub mod systemd;
 
 fn render(file: &File, values: &serde_json::Value) -> anyhow::Result<()> {
     if let Some(filename) = file.path().to_str().unwrap().strip_suffix(".handlebars") {
@@ -55,6 +56,7 @@ pub fn init_app() -> App<'static, 'static> {
         .subcommand(rails::init_subcommand())
         .subcommand(svelte::init_subcommand())
         .subcommand(proxy::init_subcommand())
+        .subcommand(systemd::init_subcommand())
         .about("Initialize directory with exogress configuration")
 }
 
@@ -64,6 +66,7 @@ pub fn handle_subcommand(args: &ArgMatches) {
     rails::handle_subcommand(args);
     svelte::handle_subcommand(args);
     proxy::handle_subcommand(args);
+    systemd::handle_subcommand(args);
     match args.subcommand_name() {
         Some(subcommand_name) => {
             println!("Platform {} is not supported", subcommand_name);
@@ -1,4 +1,7 @@
-use crate::init::from_skeleton;
+use crate::{
+    error::{parse_required_arg, CliError, OrExit},
+    init::from_skeleton,
+};
 use clap::{App, Arg, ArgMatches};
 use include_dir::{include_dir, Dir};
 use serde_json::json;
@@ -27,15 +30,8 @@ pub fn generate(port: u16) -> anyhow::Result<()> {
 
 pub fn handle_subcommand(args: &ArgMatches) {
     if let Some(app) = args.subcommand_matches(SUBCOMMAND) {
-        match app.value_of("port").unwrap().parse::<u16>() {
-            Ok(port) => {
-                generate(port).expect("could not generate");
-            }
-            Err(_) => {
-                println!("Bad port provided");
-                std::process::exit(0);
-            }
-        }
+        let port: u16 = parse_required_arg(app, "port", "--port").or_exit();
+        generate(port).map_err(CliError::config).or_exit();
         println!("Configuration generated");
         std::process::exit(0);
     }
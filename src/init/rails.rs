@@ -1,4 +1,7 @@
-use crate::init::from_skeleton;
+use crate::{
+    error::{CliError, OrExit},
+    init::from_skeleton,
+};
 use clap::{App, ArgMatches};
 use include_dir::{include_dir, Dir};
 
@@ -16,7 +19,7 @@ pub fn generate() -> anyhow::Result<()> {
 
 pub fn handle_subcommand(args: &ArgMatches) {
     if args.subcommand_matches(SUBCOMMAND).is_some() {
-        generate().expect("could not generate");
+        generate().map_err(CliError::config).or_exit();
         println!("Configuration generated");
         std::process::exit(0);
     }
@@ -0,0 +1,173 @@
+use crate::{
+    error::{parse_duration_arg, CliError, OrExit},
+    init::from_skeleton,
+};
+use anyhow::Context;
+use clap::{App, Arg, ArgMatches};
+use include_dir::{include_dir, Dir};
+use serde_json::json;
+use std::time::Duration;
+
+const SUBCOMMAND: &str = "systemd";
+
+/// Time for exogress itself to stop after the command is stopped
+const STOP_MARGIN: Duration = Duration::from_secs(20);
+
+pub fn init_subcommand() -> App<'static, 'static> {
+    App::new(SUBCOMMAND)
+        .arg(
+            Arg::with_name("env_file")
+                .long("env-file")
+                .value_name("FILE")
+                .help("EnvironmentFile with EXG_* variables, e.g. credentials")
+                .default_value("/etc/exogress/exogress.env")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("user")
+                .long("user")
+                .value_name("USER")
+                .help("User to run the service as")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("working_directory")
+                .long("working-directory")
+                .value_name("DIR")
+                .help("Directory with Exofile.yml [default: current directory]")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("watchdog_sec")
+                .long("watchdog-sec")
+                .value_name("DURATION")
+                .help("WatchdogSec of the service. The service is restarted if the process hangs for this long, 0 disables the watchdog")
+                .default_value("30s")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stop_timeout")
+                .long("stop-timeout")
+                .value_name("DURATION")
+                .help("Time to wait for the command to stop, passed to `exogress spawn`. TimeoutStopSec of the service is derived from it")
+                .default_value("10s")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("command")
+                .help("Command to spawn alongside exogress")
+                .multiple(true)
+                .last(true)
+                .takes_value(true),
+        )
+        .about("Generate exogress.service unit file for systemd")
+}
+
+static SKELETON: Dir = include_dir!("platforms_templates/systemd");
+
+/// Quote the argument for the systemd command line, if needed
+fn quote(arg: &str) -> String {
+    // specifiers, e.g. `%h`, are expanded even in quotes
+    let arg = arg.replace('%', "%%");
+
+    if !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\' || c == '$')
+    {
+        return arg;
+    }
+
+    format!(
+        "\"{}\"",
+        arg.replace('\\', r"\\")
+            .replace('"', r#"\""#)
+            .replace('$', "$$")
+    )
+}
+
+pub fn generate(
+    args: &ArgMatches,
+    watchdog_sec: Duration,
+    stop_timeout: Duration,
+) -> anyhow::Result<()> {
+    let exe = std::env::current_exe().context("could not find exogress executable")?;
+    let working_directory = match args.value_of("working_directory") {
+        Some(dir) => dir.to_string(),
+        None => std::env::current_dir()?.to_string_lossy().to_string(),
+    };
+
+    let mut exec_start = vec![
+        exe.to_string_lossy().to_string(),
+        "spawn".to_string(),
+        "--stop-timeout".to_string(),
+        humantime::format_duration(stop_timeout).to_string(),
+    ];
+    if let Some(command) = args.values_of("command") {
+        exec_start.push("--".to_string());
+        exec_start.extend(command.map(|s| s.to_string()));
+    }
+    let exec_start = exec_start
+        .iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    from_skeleton(
+        &SKELETON,
+        json!({
+            "exec_start": exec_start,
+            "env_file": args.value_of("env_file").unwrap(),
+            "working_directory": working_directory,
+            "user": args.value_of("user"),
+            "watchdog_sec": if watchdog_sec.as_secs() > 0 {
+                Some(format!("{}s", watchdog_sec.as_secs()))
+            } else {
+                None
+            },
+            "timeout_stop": humantime::format_duration(stop_timeout + STOP_MARGIN).to_string(),
+        }),
+    )
+}
+
+pub fn handle_subcommand(args: &ArgMatches) {
+    if let Some(app) = args.subcommand_matches(SUBCOMMAND) {
+        let watchdog_sec = parse_duration_arg(app, "watchdog_sec", "--watchdog-sec").or_exit();
+        let stop_timeout = parse_duration_arg(app, "stop_timeout", "--stop-timeout").or_exit();
+        generate(app, watchdog_sec, stop_timeout)
+            .map_err(CliError::config)
+            .or_exit();
+        println!("exogress.service generated. Install it with:");
+        println!("  sudo cp exogress.service /etc/systemd/system/");
+        println!("  sudo systemctl daemon-reload");
+        println!("  sudo systemctl enable --now exogress");
+        std::process::exit(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_args_are_not_quoted() {
+        assert_eq!(quote("/usr/bin/exogress"), "/usr/bin/exogress");
+        assert_eq!(quote("--stop-timeout"), "--stop-timeout");
+    }
+
+    #[test]
+    fn special_chars_are_quoted() {
+        assert_eq!(quote(""), r#""""#);
+        assert_eq!(quote("a b"), r#""a b""#);
+        assert_eq!(quote(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote(r"a\b"), r#""a\\b""#);
+        assert_eq!(quote("$HOME"), r#""$$HOME""#);
+    }
+
+    #[test]
+    fn percent_is_escaped() {
+        assert_eq!(quote("+%s"), "+%%s");
+        assert_eq!(quote("/run/secrets/%i"), "/run/secrets/%%i");
+        assert_eq!(quote("date +%s %H"), r#""date +%%s %%H""#);
+    }
+}
@@ -1,11 +1,15 @@
-use crate::{add_authentication_args, extract_authentication_args, Authentication};
+use crate::{
+    add_authentication_args, env_file,
+    error::{CliError, OrExit},
+    extract_authentication_args, Authentication,
+};
 use anyhow::anyhow;
 use clap::{App, Arg, ArgMatches};
 use exogress_common::{api::SingleInvalidationRequest, client_core::api::ApiClient};
 use tokio::runtime::Runtime;
 
 pub fn invalidations_args<'a>() -> clap::App<'a, 'a> {
-    add_authentication_args(
+    env_file::add_args(add_authentication_args(
         App::new("invalidate")
             .about("invalidate cache records")
             .arg(
@@ -15,7 +19,7 @@ pub fn invalidations_args<'a>() -> clap::App<'a, 'a> {
                     .last(true)
                     .multiple(true),
             ),
-    )
+    ))
 }
 
 fn parse_invalidation_params(
@@ -53,7 +57,9 @@ fn parse_invalidation_params(
 }
 
 pub fn handle_subcommand(args: &ArgMatches) {
-    let rt = Runtime::new().unwrap();
+    let rt = Runtime::new()
+        .map_err(|e| CliError::os(format!("could not start the runtime: {}", e)))
+        .or_exit();
 
     let Authentication {
         access_key_id,
@@ -62,7 +68,7 @@ pub fn handle_subcommand(args: &ArgMatches) {
         project,
         api_endpoint,
         ..
-    } = extract_authentication_args(args).expect("bad authentication data provided");
+    } = extract_authentication_args(args).or_exit();
 
     let api = ApiClient::new(
         &project,
@@ -71,40 +77,33 @@ pub fn handle_subcommand(args: &ArgMatches) {
         &secret_access_key,
         &api_endpoint,
     )
-    .unwrap();
+    .map_err(|e| CliError::Network(format!("{:#}", e)))
+    .or_exit();
+
+    let invalidations = args
+        .values_of("invalidations")
+        .ok_or_else(|| CliError::usage("invalidations", "no invalidations provided"))
+        .or_exit();
 
-    let invalidations = args.values_of("invalidations").expect("bad invalidations");
+    let invalidations: Vec<SingleInvalidationRequest> = parse_invalidation_params(invalidations)
+        .collect::<Result<_, _>>()
+        .map_err(|e| CliError::usage("invalidations", format!("bad invalidation {:#}", e)))
+        .or_exit();
 
-    let parsed_invalidations: Result<Vec<SingleInvalidationRequest>, _> =
-        parse_invalidation_params(invalidations).collect::<Result<_, _>>();
+    println!("Sending invalidation request...");
 
-    match parsed_invalidations {
-        Err(e) => {
-            println!("Bad invalidation: {}", e);
-            std::process::exit(-1);
-        }
-        Ok(invalidations) => {
-            println!("Sending invalidation request...");
+    let spinner = indicatif::ProgressBar::new_spinner();
 
-            let spinner = indicatif::ProgressBar::new_spinner();
+    spinner.enable_steady_tick(100);
 
-            spinner.enable_steady_tick(100);
+    let res = rt.block_on(async move { api.invalidate(&invalidations).await });
 
-            let res = rt.block_on(async move { api.invalidate(&invalidations).await });
+    spinner.finish();
 
-            spinner.finish();
+    res.map_err(|e| CliError::Network(format!("{:#}", e)))
+        .or_exit();
 
-            match res {
-                Ok(_) => {
-                    println!("Succeeded");
-                }
-                Err(e) => {
-                    println!("{:?}", e);
-                    std::process::exit(-1);
-                }
-            }
-        }
-    }
+    println!("Succeeded");
 
     std::process::exit(0);
 }
@@ -0,0 +1,260 @@
+use crate::error::CliError;
+use clap::{crate_version, Arg, ArgMatches};
+use exogress_common::entities::{LabelName, LabelValue};
+use hashbrown::HashMap;
+use std::{collections::BTreeMap, fs, process::Command, str::FromStr};
+
+const HOSTNAME: &str = "hostname";
+const CONTAINER_ID: &str = "container-id";
+const GIT_COMMIT: &str = "git-commit";
+const CLI_VERSION: &str = "cli-version";
+
+pub fn add_args<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a> {
+    app.arg(
+        Arg::with_name("label")
+            .long("label")
+            .short("l")
+            .value_name("KEY=VALUE")
+            .help("Attach label to running instance")
+            .multiple(true)
+            .required(false)
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("labels_file")
+            .long("labels-file")
+            .value_name("FILE")
+            .help("Attach labels from YAML or JSON map")
+            .env("EXG_LABELS_FILE")
+            .required(false)
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("auto_label")
+            .long("auto-label")
+            .value_name("KIND")
+            .help("Attach automatically detected label")
+            .env("EXG_AUTO_LABELS")
+            .possible_values(&[HOSTNAME, CONTAINER_ID, GIT_COMMIT, CLI_VERSION])
+            .multiple(true)
+            .use_delimiter(true)
+            .required(false)
+            .takes_value(true),
+    )
+}
+
+fn validate(name: &str, value: &str, arg: &str) -> Result<(LabelName, LabelValue), CliError> {
+    if name.is_empty() {
+        return Err(CliError::usage(
+            arg,
+            format!("empty label name in `{}={}`", name, value),
+        ));
+    }
+
+    Ok((
+        LabelName::from_str(name)
+            .map_err(|e| CliError::usage(arg, format!("bad label name `{}`: {}", name, e)))?,
+        LabelValue::from_str(value)
+            .map_err(|e| CliError::usage(arg, format!("bad label value `{}`: {}", value, e)))?,
+    ))
+}
+
+/// Parse `KEY=VALUE`. Everything after the first `=` is the value, and
+/// environment variables in it are expanded.
+fn parse_label(label: &str) -> Result<(LabelName, LabelValue), CliError> {
+    let (name, value) = label.split_once('=').ok_or_else(|| {
+        CliError::usage(
+            "--label",
+            format!("bad label `{}`, expected KEY=VALUE", label),
+        )
+    })?;
+    let expanded_value = shellexpand::env(value)
+        .map_err(|e| CliError::usage("--label", format!("`{}`: {}", label, e)))?;
+
+    validate(name, &expanded_value, "--label")
+}
+
+fn load_labels_file(path: &str) -> Result<Vec<(LabelName, LabelValue)>, CliError> {
+    const ARG: &str = "--labels-file (EXG_LABELS_FILE)";
+
+    let content = fs::read_to_string(path)
+        .map_err(|e| CliError::usage(ARG, format!("could not read {}: {}", path, e)))?;
+
+    // JSON is a subset of YAML, so both are parsed the same way
+    let map: BTreeMap<String, serde_yaml::Value> = serde_yaml::from_str(&content)
+        .map_err(|e| CliError::Config(format!("bad labels file {}: {}", path, e)))?;
+
+    map.into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                serde_yaml::Value::String(s) => s,
+                serde_yaml::Value::Number(n) => n.to_string(),
+                serde_yaml::Value::Bool(b) => b.to_string(),
+                _ => {
+                    return Err(CliError::Config(format!(
+                        "bad labels file {}: value of `{}` should be a string",
+                        path, name
+                    )))
+                }
+            };
+            validate(&name, &value, ARG)
+        })
+        .collect()
+}
+
+fn detect_hostname() -> Option<String> {
+    hostname::get().ok()?.into_string().ok()
+}
+
+/// Find container ID in cgroups (cgroup v1), or in mounts (cgroup v2)
+fn detect_container_id() -> Option<String> {
+    let is_container_id = |s: &str| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit());
+
+    let cgroup = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+    let from_cgroup = cgroup
+        .lines()
+        .flat_map(|line| line.split(|c| c == '/' || c == '-' || c == '.'))
+        .find(|s| is_container_id(s));
+
+    if let Some(id) = from_cgroup {
+        return Some(id.to_string());
+    }
+
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    mountinfo
+        .lines()
+        .filter(|line| line.contains("/containers/"))
+        .flat_map(|line| line.split('/'))
+        .find(|s| is_container_id(s))
+        .map(|s| s.to_string())
+}
+
+fn detect_git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
+
+fn auto_label(kind: &str) -> Option<String> {
+    match kind {
+        HOSTNAME => detect_hostname(),
+        CONTAINER_ID => detect_container_id(),
+        GIT_COMMIT => detect_git_commit(),
+        CLI_VERSION => Some(crate_version!().to_string()),
+        _ => None,
+    }
+}
+
+/// Collect labels from all sources. Automatic labels are overridden by labels
+/// from the file, which are overridden by `--label`.
+pub fn extract_labels(matches: &ArgMatches) -> Result<HashMap<LabelName, LabelValue>, CliError> {
+    let mut labels = HashMap::new();
+
+    for kind in matches.values_of("auto_label").into_iter().flatten() {
+        match auto_label(kind) {
+            Some(value) => {
+                let (name, value) = validate(kind, &value, "--auto-label (EXG_AUTO_LABELS)")?;
+                labels.insert(name, value);
+            }
+            None => {
+                warn!("could not detect {}, label is not attached", kind);
+            }
+        }
+    }
+
+    if let Some(path) = matches.value_of("labels_file") {
+        labels.extend(load_labels_file(path)?);
+    }
+
+    for label in matches.values_of("label").into_iter().flatten() {
+        let (name, value) = parse_label(label)?;
+        labels.insert(name, value);
+    }
+
+    Ok(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::App;
+
+    fn label(name: &str, value: &str) -> (LabelName, LabelValue) {
+        validate(name, value, "--label").unwrap()
+    }
+
+    fn labels_file(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "exogress-labels-{}-{}.yml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn value_with_equals_sign() {
+        assert_eq!(
+            parse_label("url=https://x?a=b").unwrap(),
+            label("url", "https://x?a=b")
+        );
+    }
+
+    #[test]
+    fn missing_equals_sign() {
+        assert!(matches!(parse_label("url"), Err(CliError::Usage { .. })));
+    }
+
+    #[test]
+    fn empty_name() {
+        assert!(matches!(parse_label("=value"), Err(CliError::Usage { .. })));
+    }
+
+    #[test]
+    fn flags_override_file() {
+        let path = labels_file("override", "env: staging\nteam: core\n");
+        let matches = add_args(App::new("spawn")).get_matches_from(vec![
+            "spawn",
+            "--labels-file",
+            path.as_str(),
+            "--label",
+            "env=prod",
+        ]);
+
+        let labels = extract_labels(&matches).unwrap();
+
+        assert_eq!(labels.len(), 2);
+        let (env, prod) = label("env", "prod");
+        assert_eq!(labels.get(&env), Some(&prod));
+        let (team, core) = label("team", "core");
+        assert_eq!(labels.get(&team), Some(&core));
+    }
+
+    #[test]
+    fn scalar_values_in_file() {
+        let path = labels_file("scalars", "{\"replicas\": 3, \"canary\": true}");
+
+        // map keys are sorted
+        assert_eq!(
+            load_labels_file(&path).unwrap(),
+            vec![label("canary", "true"), label("replicas", "3")]
+        );
+    }
+
+    #[test]
+    fn non_scalar_values_in_file() {
+        let path = labels_file("non-scalars", "tags: [a, b]\n");
+        assert!(matches!(load_labels_file(&path), Err(CliError::Config(_))));
+
+        let path = labels_file("nested", "owner:\n  team: core\n");
+        assert!(matches!(load_labels_file(&path), Err(CliError::Config(_))));
+    }
+}
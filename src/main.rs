@@ -3,22 +3,45 @@ extern crate tracing;
 #[macro_use]
 extern crate shadow_clone;
 
+mod admin;
+mod atomic_file;
+mod child_log;
+mod config;
+mod context;
+mod dns;
+mod env_file;
+mod error;
+mod exofile;
+mod gateway;
 mod init;
 mod invalidate;
+mod labels;
+mod metrics;
+mod procfile;
+mod readiness;
+mod secrets;
+mod status;
+mod supervisor;
+mod systemd;
 mod termination;
 
-use std::{collections::VecDeque, process::Stdio};
-
-use crate::termination::StopReason;
+use crate::{
+    child_log::{ChildLogConfig, ChildLogFormat},
+    error::{parse_arg, parse_duration_arg, parse_required_arg, CliError, OrExit},
+    exofile::Exofile,
+    readiness::{ProbeKind, ReadinessProbe},
+    status::{ClientState, SharedStatus},
+    supervisor::{ChildControl, ChildSignal, RestartPolicy, Supervisor, SupervisorConfig},
+    termination::{stop_signal_listener, StopReason},
+};
 use clap::{crate_version, App, Arg, ArgMatches};
 use exogress_common::{
     client_core::{Client, DEFAULT_CLOUD_ENDPOINT},
-    common_utils::termination::stop_signal_listener,
-    entities::{LabelName, LabelValue, ProfileName},
+    entities::ProfileName,
 };
-use futures::{future, future::Either, select_biased, FutureExt};
+use futures::{future, FutureExt, StreamExt};
 use stop_handle::stop_handle;
-use tokio::{process::Command, runtime::Builder};
+use tokio::runtime::Builder;
 
 use crate::invalidate::invalidations_args;
 use exogress_common::{
@@ -27,22 +50,25 @@ use exogress_common::{
 };
 use futures::channel::mpsc;
 use hashbrown::HashMap;
-use std::str::FromStr;
-use trust_dns_resolver::{TokioAsyncResolver, TokioHandle};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
 use url::Url;
 
 #[cfg(feature = "jemalloc")]
 #[global_allocator]
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
-pub fn add_authentication_args<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a> {
+/// Account, project and access key args, which make a credentials context
+pub fn add_credentials_args<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a> {
     app.arg(
         Arg::with_name("project")
             .long("project")
             .value_name("STRING")
             .help("Project")
             .env("EXG_PROJECT")
-            .required(true)
+            .required(false)
             .takes_value(true),
     )
     .arg(
@@ -52,7 +78,7 @@ pub fn add_authentication_args<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a>
             .help("ACCESS_KEY_ID")
             .env("EXG_ACCESS_KEY_ID")
             .hide_env_values(true)
-            .required(true)
+            .required(false)
             .takes_value(true),
     )
     .arg(
@@ -62,7 +88,7 @@ pub fn add_authentication_args<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a>
             .help("SECRET_ACCESS_KEY")
             .env("EXG_SECRET_ACCESS_KEY")
             .hide_env_values(true)
-            .required(true)
+            .required(false)
             .takes_value(true),
     )
     .arg(
@@ -71,55 +97,172 @@ pub fn add_authentication_args<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a>
             .value_name("STRING")
             .env("EXG_ACCOUNT")
             .help("Account")
-            .required(true)
+            .required(false)
             .takes_value(true),
     )
 }
 
+/// Authentication args are optional, because they may be taken from the
+/// credentials context
+pub fn add_authentication_args<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a> {
+    add_credentials_args(app)
+        .arg(
+            Arg::with_name("access_key_id_file")
+                .long("access-key-id-file")
+                .value_name("FILE")
+                .help("Read ACCESS_KEY_ID from the file. Re-read on SIGHUP")
+                .env("EXG_ACCESS_KEY_ID_FILE")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("secret_access_key_file")
+                .long("secret-access-key-file")
+                .value_name("FILE")
+                .help("Read SECRET_ACCESS_KEY from the file. Re-read on SIGHUP")
+                .env("EXG_SECRET_ACCESS_KEY_FILE")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("context")
+                .long("context")
+                .value_name("NAME")
+                .env("EXG_CONTEXT")
+                .help("Credentials context to take missing authentication data from")
+                .required(false)
+                .takes_value(true),
+        )
+}
+
 pub struct Authentication {
     access_key_id: AccessKeyId,
     secret_access_key: String,
+    access_key_id_file: Option<PathBuf>,
+    secret_access_key_file: Option<PathBuf>,
     account: AccountName,
     project: ProjectName,
     cloud_endpoint: Url,
     api_endpoint: Url,
 }
 
-pub fn extract_authentication_args(spawn_matches: &ArgMatches) -> anyhow::Result<Authentication> {
-    let access_key_id: AccessKeyId = spawn_matches
-        .value_of("access_key_id")
-        .expect("access_key_id is not set")
-        .parse()
-        .expect("access_key_id is not ULID");
+/// Value of the argument, or content of the file, if the file argument is set
+fn explicit_value(
+    matches: &ArgMatches,
+    arg: &str,
+    file_arg: Option<&str>,
+) -> Result<Option<String>, CliError> {
+    if let Some(path) = file_arg.and_then(|file_arg| matches.value_of(file_arg)) {
+        return Ok(Some(
+            secrets::read_secret_file(Path::new(path))
+                .map_err(|e| CliError::Auth(format!("{:#}", e)))?,
+        ));
+    }
 
-    let secret_access_key = spawn_matches
-        .value_of("secret_access_key")
-        .expect("secret_access_key is not set")
-        .to_string();
+    Ok(matches.value_of(arg).map(|v| v.to_string()))
+}
 
-    let account = spawn_matches
-        .value_of("account")
-        .expect("account is not set")
-        .parse()?;
+fn value_or_context(
+    explicit: Option<String>,
+    from_context: Option<&String>,
+    arg: &str,
+) -> Result<String, CliError> {
+    explicit.or_else(|| from_context.cloned()).ok_or_else(|| {
+        CliError::Auth(format!(
+            "{} is not set, and no credentials context is available",
+            arg
+        ))
+    })
+}
 
-    let project = spawn_matches
-        .value_of("project")
-        .expect("project is not set")
-        .parse()?;
+pub fn extract_authentication_args(spawn_matches: &ArgMatches) -> Result<Authentication, CliError> {
+    const ACCESS_KEY_ID_ARG: &str = "--access-key-id (EXG_ACCESS_KEY_ID)";
+    const SECRET_ACCESS_KEY_ARG: &str = "--secret-access-key (EXG_SECRET_ACCESS_KEY)";
+    const ACCOUNT_ARG: &str = "--account (EXG_ACCOUNT)";
+    const PROJECT_ARG: &str = "--project (EXG_PROJECT)";
+
+    let access_key_id_file = spawn_matches
+        .value_of("access_key_id_file")
+        .map(PathBuf::from);
+    let secret_access_key_file = spawn_matches
+        .value_of("secret_access_key_file")
+        .map(PathBuf::from);
+
+    let access_key_id = explicit_value(spawn_matches, "access_key_id", Some("access_key_id_file"))?;
+    let secret_access_key = explicit_value(
+        spawn_matches,
+        "secret_access_key",
+        Some("secret_access_key_file"),
+    )?;
+    let account = explicit_value(spawn_matches, "account", None)?;
+    let project = explicit_value(spawn_matches, "project", None)?;
+
+    let is_context_required = access_key_id.is_none()
+        || secret_access_key.is_none()
+        || account.is_none()
+        || project.is_none();
+
+    let credentials_context = if is_context_required {
+        let credentials = context::credentials_path()
+            .and_then(|path| context::Credentials::load(&path))
+            .map_err(CliError::config)?;
+        credentials
+            .resolve(spawn_matches.value_of("context"))
+            .map_err(|e| CliError::usage("--context (EXG_CONTEXT)", e))?
+            .cloned()
+    } else {
+        None
+    };
+
+    let access_key_id: AccessKeyId = value_or_context(
+        access_key_id,
+        credentials_context.as_ref().map(|c| &c.access_key_id),
+        ACCESS_KEY_ID_ARG,
+    )?
+    .parse()
+    .map_err(|_| CliError::Auth(format!("{}: not a valid ULID", ACCESS_KEY_ID_ARG)))?;
+
+    let secret_access_key = value_or_context(
+        secret_access_key,
+        credentials_context.as_ref().map(|c| &c.secret_access_key),
+        SECRET_ACCESS_KEY_ARG,
+    )?;
+
+    let account = value_or_context(
+        account,
+        credentials_context.as_ref().map(|c| &c.account),
+        ACCOUNT_ARG,
+    )?
+    .parse()
+    .map_err(|e| CliError::usage(ACCOUNT_ARG, e))?;
+
+    let project = value_or_context(
+        project,
+        credentials_context.as_ref().map(|c| &c.project),
+        PROJECT_ARG,
+    )?
+    .parse()
+    .map_err(|e| CliError::usage(PROJECT_ARG, e))?;
 
     let cloud_endpoint: Url = std::env::var("EXG_CLOUD_ENDPOINT")
         .unwrap_or_else(|_| DEFAULT_CLOUD_ENDPOINT.to_string())
         .parse()
-        .expect("bad cloud endpoint provided");
+        .map_err(|e| CliError::usage("EXG_CLOUD_ENDPOINT", e))?;
 
     let api_endpoint: Url = std::env::var("EXG_CLOUD_API_OVERRIDE")
         .ok()
-        .map(|var| var.parse().expect("bad cloud endpoint provided"))
+        .map(|var| {
+            var.parse()
+                .map_err(|e| CliError::usage("EXG_CLOUD_API_OVERRIDE", e))
+        })
+        .transpose()?
         .unwrap_or_else(|| cloud_endpoint.clone());
 
     Ok(Authentication {
         access_key_id,
         secret_access_key,
+        access_key_id_file,
+        secret_access_key_file,
         account,
         project,
         cloud_endpoint,
@@ -127,6 +270,62 @@ pub fn extract_authentication_args(spawn_matches: &ArgMatches) -> anyhow::Result
     })
 }
 
+/// Re-read credentials from files, if they are provided through files
+fn reread_credentials(
+    access_key_id_file: Option<&Path>,
+    secret_access_key_file: Option<&Path>,
+) -> anyhow::Result<(Option<AccessKeyId>, Option<String>)> {
+    let access_key_id = access_key_id_file
+        .map(|path| -> anyhow::Result<AccessKeyId> {
+            secrets::read_secret_file(path)?
+                .parse()
+                .map_err(|_| anyhow::anyhow!("access key id from {} is not ULID", path.display()))
+        })
+        .transpose()?;
+    let secret_access_key = secret_access_key_file
+        .map(secrets::read_secret_file)
+        .transpose()?;
+
+    Ok((access_key_id, secret_access_key))
+}
+
+/// Forward config reload requests to the client and count them. Requests
+/// come both from SIGHUP and from the config file watcher of the client.
+/// The result of a reload is whether the config is valid, since the client
+/// loads the same file.
+async fn forward_config_reloads(
+    mut requests: mpsc::UnboundedReceiver<()>,
+    client_tx: mpsc::UnboundedSender<()>,
+    config_path: String,
+    status: SharedStatus,
+) {
+    while let Some(()) = requests.next().await {
+        // systemd is notified only after the startup notification, which
+        // is sent once the client is started
+        let is_running = status.is_client_running();
+        if is_running {
+            systemd::notify_reloading();
+        }
+        match Exofile::load(&config_path) {
+            Ok(exofile) => {
+                status.metrics().record_config_reload(true);
+                status.set_config(Some(exofile.name), Some(exofile.revision));
+            }
+            Err(e) => {
+                status.metrics().record_config_reload(false);
+                warn!("config is not valid: {:#}", e);
+            }
+        }
+        if is_running {
+            systemd::notify_ready();
+        }
+
+        if client_tx.unbounded_send(()).is_err() {
+            break;
+        }
+    }
+}
+
 pub fn main() {
     let spawn_app = App::new("spawn")
         .about("spawn exogress client")
@@ -147,12 +346,125 @@ pub fn main() {
                 .takes_value(true),
         )
         .arg(
-            Arg::with_name("label")
-                .long("label")
-                .short("l")
-                .value_name("KEY=VALUE")
-                .help("Attach label to running instance")
-                .multiple(true)
+            Arg::with_name("restart")
+                .long("restart")
+                .value_name("POLICY")
+                .help("Restart policy for the command")
+                .env("EXG_RESTART")
+                .possible_values(RestartPolicy::VARIANTS)
+                .default_value("never")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_restarts")
+                .long("max-restarts")
+                .value_name("NUMBER")
+                .help("Give up after this number of restarts")
+                .env("EXG_MAX_RESTARTS")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("restart_backoff")
+                .long("restart-backoff")
+                .value_name("DURATION")
+                .help("Initial delay before restarting the command, doubled on every restart")
+                .default_value("1s")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("restart_backoff_max")
+                .long("restart-backoff-max")
+                .value_name("DURATION")
+                .help("Maximum delay before restarting the command")
+                .default_value("1m")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stop_timeout")
+                .long("stop-timeout")
+                .value_name("DURATION")
+                .help("Time to wait for the command to stop before killing it")
+                .env("EXG_STOP_TIMEOUT")
+                .default_value("10s")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("forward_sighup")
+                .long("forward-sighup")
+                .help("Forward SIGHUP to the command in addition to reloading config")
+                .takes_value(false)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("readiness_probe")
+                .long("readiness-probe")
+                .value_name("KIND")
+                .help("Wait for upstreams from the config to accept connections before serving traffic")
+                .env("EXG_READINESS_PROBE")
+                .possible_values(ProbeKind::VARIANTS)
+                .default_value("none")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("readiness_path")
+                .long("readiness-path")
+                .value_name("PATH")
+                .help("Path requested by the http readiness probe")
+                .env("EXG_READINESS_PATH")
+                .default_value("/")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("readiness_timeout")
+                .long("readiness-timeout")
+                .value_name("DURATION")
+                .help("Start serving traffic anyway if upstreams are not ready after this time")
+                .env("EXG_READINESS_TIMEOUT")
+                .default_value("60s")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("child_log_format")
+                .long("child-log-format")
+                .value_name("FORMAT")
+                .help("Format of the command output forwarded to stdout and stderr")
+                .env("EXG_CHILD_LOG_FORMAT")
+                .possible_values(ChildLogFormat::VARIANTS)
+                .default_value("raw")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("child_log_prefix")
+                .long("child-log-prefix")
+                .value_name("STRING")
+                .help("Prefix each line of the command output, or set `prefix` field in the json format")
+                .env("EXG_CHILD_LOG_PREFIX")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("child_log_timestamps")
+                .long("child-log-timestamps")
+                .help("Prepend timestamp to each line of the command output")
+                .takes_value(false)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("procfile")
+                .long("procfile")
+                .value_name("FILE")
+                .help("Run all processes from the Procfile instead of a single command")
+                .env("EXG_PROCFILE")
+                .conflicts_with("command")
                 .required(false)
                 .takes_value(true),
         )
@@ -163,7 +475,8 @@ pub fn main() {
                 .multiple(true),
         );
 
-    let spawn_app = add_authentication_args(spawn_app);
+    let spawn_app = dns::add_args(admin::add_args(spawn_app));
+    let spawn_app = labels::add_args(env_file::add_args(add_authentication_args(spawn_app)));
 
     let invalidate_subcommand = invalidations_args();
 
@@ -189,18 +502,34 @@ pub fn main() {
                 .takes_value(true),
         )
         .subcommand(init::init_app())
+        .subcommand(config::config_app())
+        .subcommand(gateway::dev::dev_app())
+        .subcommand(context::context_app())
         .subcommand(invalidate_subcommand)
+        .subcommand(admin::healthcheck_app())
         .subcommand(exogress_common::common_utils::clap::threads::add_args(
             exogress_common::common_utils::clap::log::add_args(spawn_app),
         ));
 
     let mut app = exogress_common::common_utils::clap::autocompletion::add_args(app);
 
-    let matches = app.clone().get_matches();
+    // env files are loaded after the first pass, and arguments are parsed
+    // again, so that `EXG_*` variables from the files are picked up
+    if let Ok(matches) = app.clone().get_matches_safe() {
+        env_file::load_from_matches(&matches)
+            .map_err(CliError::config)
+            .or_exit();
+    }
+
+    let matches = app
+        .clone()
+        .get_matches_safe()
+        .unwrap_or_else(|e| error::exit_on_args_error(e));
 
     let config_path = matches
         .value_of("config")
-        .expect("--config is not set")
+        .ok_or_else(|| CliError::usage("--config (EXG_CONFIG_FILE)", "is not set"))
+        .or_exit()
         .to_string();
 
     if let Some(init_subcommand) = matches.subcommand_matches("init") {
@@ -208,11 +537,27 @@ pub fn main() {
         std::process::exit(0);
     }
 
+    if let Some(config_subcommand) = matches.subcommand_matches("config") {
+        config::handle_subcommand(config_subcommand);
+    }
+
+    if let Some(dev_subcommand) = matches.subcommand_matches("dev") {
+        gateway::dev::handle_subcommand(dev_subcommand);
+    }
+
+    if let Some(context_subcommand) = matches.subcommand_matches("context") {
+        context::handle_subcommand(context_subcommand);
+    }
+
     if let Some(invalidate_subcommand) = matches.subcommand_matches("invalidate") {
         invalidate::handle_subcommand(invalidate_subcommand);
         std::process::exit(0);
     }
 
+    if let Some(healthcheck_subcommand) = matches.subcommand_matches("healthcheck") {
+        admin::handle_subcommand(healthcheck_subcommand);
+    }
+
     exogress_common::common_utils::clap::autocompletion::handle_autocompletion(
         &mut app.clone(),
         &matches,
@@ -224,12 +569,18 @@ pub fn main() {
     } else {
         app.print_long_help().unwrap();
         println!();
-        std::process::exit(1);
+        std::process::exit(error::USAGE_ERROR_EXIT_CODE);
     };
 
     let gw_tunnels_port: u16 = std::env::var("EXG_GW_TUNNELS_PORT")
-        .map(|v| v.parse().expect("bad EXG_GW_TUNNELS_PORT"))
-        .unwrap_or_else(|_| 443);
+        .ok()
+        .map(|v| {
+            v.parse()
+                .map_err(|e| CliError::usage("EXG_GW_TUNNELS_PORT", e))
+        })
+        .transpose()
+        .or_exit()
+        .unwrap_or(443);
 
     exogress_common::common_utils::clap::log::handle(&spawn_matches, "exogress");
     let num_threads = exogress_common::common_utils::clap::threads::extract_matches(&spawn_matches);
@@ -239,152 +590,386 @@ pub fn main() {
         .worker_threads(num_threads)
         .thread_name("exogress-reactor")
         .build()
-        .unwrap();
+        .map_err(|e| CliError::os(format!("could not start the runtime: {}", e)))
+        .or_exit();
 
     let should_watch_config = !spawn_matches.is_present("no_watch_config");
 
     let Authentication {
         access_key_id,
         secret_access_key,
+        access_key_id_file,
+        secret_access_key_file,
         account,
         project,
         cloud_endpoint,
         ..
-    } = extract_authentication_args(spawn_matches).expect("bad authentication data provided");
+    } = extract_authentication_args(spawn_matches).or_exit();
 
-    let profile: Option<ProfileName> = spawn_matches
-        .value_of("profile")
-        .map(|p| p.parse().expect("Bad profile name"));
+    let profile: Option<ProfileName> =
+        parse_arg(spawn_matches, "profile", "--profile (EXG_PROFILE)").or_exit();
 
-    let labels = spawn_matches
-        .values_of("label")
-        .map(|v| {
-            v.map(|v| {
-                let mut kv = v.split('=');
-                let k = kv.next().expect("bad label format");
-                let v = kv.next().expect("bad label format");
-                assert!(kv.next().is_none(), "bad label format");
-                let expanded_v = shellexpand::env(v).expect("Could not expand value");
-                (
-                    LabelName::from_str(k).expect("bad label name format"),
-                    LabelValue::from_str(&expanded_v).expect("bad label value"),
-                )
-            })
-        })
-        .into_iter()
-        .flatten()
-        .collect::<HashMap<LabelName, LabelValue>>();
+    let dns_settings = dns::extract(spawn_matches).or_exit();
+
+    let labels = labels::extract_labels(spawn_matches).or_exit();
+
+    let supervisor_config = SupervisorConfig {
+        policy: parse_required_arg(spawn_matches, "restart", "--restart (EXG_RESTART)").or_exit(),
+        max_restarts: parse_arg(
+            spawn_matches,
+            "max_restarts",
+            "--max-restarts (EXG_MAX_RESTARTS)",
+        )
+        .or_exit(),
+        backoff_initial: parse_duration_arg(spawn_matches, "restart_backoff", "--restart-backoff")
+            .or_exit(),
+        backoff_max: parse_duration_arg(
+            spawn_matches,
+            "restart_backoff_max",
+            "--restart-backoff-max",
+        )
+        .or_exit(),
+        stop_timeout: parse_duration_arg(
+            spawn_matches,
+            "stop_timeout",
+            "--stop-timeout (EXG_STOP_TIMEOUT)",
+        )
+        .or_exit(),
+        log: ChildLogConfig {
+            format: parse_required_arg(
+                spawn_matches,
+                "child_log_format",
+                "--child-log-format (EXG_CHILD_LOG_FORMAT)",
+            )
+            .or_exit(),
+            prefix: spawn_matches
+                .value_of("child_log_prefix")
+                .map(|s| s.to_string()),
+            timestamps: spawn_matches.is_present("child_log_timestamps"),
+        },
+    };
+
+    let readiness_probe = ReadinessProbe {
+        kind: parse_required_arg(
+            spawn_matches,
+            "readiness_probe",
+            "--readiness-probe (EXG_READINESS_PROBE)",
+        )
+        .or_exit(),
+        http_path: parse_required_arg(
+            spawn_matches,
+            "readiness_path",
+            "--readiness-path (EXG_READINESS_PATH)",
+        )
+        .or_exit(),
+        timeout: parse_duration_arg(
+            spawn_matches,
+            "readiness_timeout",
+            "--readiness-timeout (EXG_READINESS_TIMEOUT)",
+        )
+        .or_exit(),
+    };
+
+    let procfile_entries = spawn_matches
+        .value_of("procfile")
+        .map(|path| procfile::load(path).map_err(CliError::config))
+        .transpose()
+        .or_exit();
+
+    let should_forward_sighup = spawn_matches.is_present("forward_sighup");
+
+    let admin_listen: Option<SocketAddr> = parse_arg(
+        spawn_matches,
+        "admin_listen",
+        "--admin-listen (EXG_ADMIN_LISTEN)",
+    )
+    .or_exit();
+
+    let active_profile = spawn_matches.value_of("profile").map(|p| p.to_string());
+
+    let shared_status = SharedStatus::new(
+        active_profile.clone(),
+        labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    );
 
     let (app_stop_handle, app_stop_wait) = stop_handle::<StopReason>();
 
-    rt.block_on(async move {
-        tokio::spawn(stop_signal_listener(app_stop_handle.clone()));
+    let stop_reason = rt.block_on(async move {
+        let (child_control_tx, child_control_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        tokio::spawn(stop_signal_listener(
+            app_stop_handle.clone(),
+            child_control_tx.clone(),
+        ));
+
+        tokio::spawn(systemd::run_watchdog(shared_status.clone()));
+
+        if let Some(admin_listen) = admin_listen {
+            let admin = admin::bind(admin_listen).or_exit();
+            tokio::spawn(admin::serve(admin, shared_status.clone()));
+        }
+
+        let exofile = match Exofile::load(&config_path) {
+            Ok(exofile) => Some(exofile),
+            Err(e) => {
+                if readiness_probe.kind != ProbeKind::None {
+                    warn!("readiness probes are disabled: {:#}", e);
+                }
+                None
+            }
+        };
+        shared_status.set_config(
+            exofile.as_ref().map(|e| e.name.clone()),
+            exofile.as_ref().map(|e| e.revision),
+        );
 
-        let (reload_config_tx, reload_config_rx) = mpsc::unbounded();
+        // SIGHUP is kept pending until the client handles it, so that it
+        // is not lost while the client waits for readiness or reconnects
+        let (hup_tx, mut hup_rx) = tokio::sync::watch::channel(());
 
         #[cfg(unix)]
         tokio::spawn({
-            shadow_clone!(reload_config_tx);
+            shadow_clone!(hup_tx, child_control_tx, shared_status);
 
             async move {
                 let mut hup_listener =
-                    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).unwrap();
+                    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                        Ok(hup_listener) => hup_listener,
+                        Err(e) => {
+                            error!("could not listen to SIGHUP: {}", e);
+                            return;
+                        }
+                    };
                 while hup_listener.recv().await.is_some() {
                     info!("SIGHUP received");
-                    reload_config_tx.unbounded_send(()).unwrap();
+                    shared_status.metrics().record_sighup();
+                    hup_tx.send(()).ok();
+                    if should_forward_sighup {
+                        child_control_tx
+                            .send(ChildControl::Signal(ChildSignal::Hangup))
+                            .ok();
+                    }
                 }
             }
         });
 
-        let resolver = TokioAsyncResolver::from_system_conf(TokioHandle).unwrap();
-
-        let process = match spawn_matches.values_of("command") {
-            Some(cmd_and_args) if cmd_and_args.len() > 0 => {
-                let mut commands_deque: VecDeque<_> = cmd_and_args.collect();
+        #[cfg(not(unix))]
+        drop(hup_tx);
 
-                // We may unwrap here because cmd_and_args is > 0
-                let mut command = Command::new(commands_deque.pop_front().unwrap());
+        let resolver = dns::build_resolver(&dns_settings).or_exit();
 
-                command
-                    .args(commands_deque)
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped());
+        let command = spawn_matches
+            .values_of("command")
+            .map(|cmd_and_args| cmd_and_args.map(|s| s.to_string()).collect::<Vec<_>>())
+            .filter(|cmd_and_args| !cmd_and_args.is_empty());
 
-                let mut child = command.spawn().expect("failed to spawn command");
+        let has_child = procfile_entries.is_some() || command.is_some();
 
-                let mut stderr = child.stderr.take().unwrap();
-                let mut stdout = child.stdout.take().unwrap();
+        let process = match (procfile_entries, command) {
+            (Some(procfile_entries), _) => {
+                let status = shared_status.clone();
 
-                let stdout_forward = async move {
-                    tokio::io::copy(&mut stdout, &mut tokio::io::stdout())
-                        .await
-                        .ok();
+                async move {
+                    StopReason::from_child_result(
+                        procfile::run(
+                            procfile_entries,
+                            supervisor_config,
+                            child_control_rx,
+                            status,
+                        )
+                        .await,
+                    )
                 }
-                .fuse();
-
-                let stderr_forward = async move {
-                    tokio::io::copy(&mut stderr, &mut tokio::io::stderr())
-                        .await
-                        .ok();
-                }
-                .fuse();
-
-                Either::Left(async move {
-                    futures::pin_mut!(stdout_forward);
-                    futures::pin_mut!(stderr_forward);
-
-                    let wait_child = child.wait().fuse();
+                .boxed()
+            }
+            (None, Some(cmd_and_args)) => {
+                let supervisor = Supervisor::new(cmd_and_args, supervisor_config, child_control_rx)
+                    .status(shared_status.register_process(None));
 
-                    futures::pin_mut!(wait_child);
+                async move { StopReason::from_child_result(supervisor.run().await) }.boxed()
+            }
+            (None, None) => {
+                info!("running in standalone mode");
+                future::pending().boxed()
+            }
+        }
+        .fuse();
 
-                    select_biased! {
-                        _ = wait_child => {}
-                        _ = stdout_forward => {}
-                        _ = stderr_forward => {}
-                    }
+        let reload_config_path = config_path.clone();
+        let build_client = move |access_key_id: AccessKeyId, secret_access_key: String| {
+            Client::builder()
+                .config_path(config_path.clone())
+                .access_key_id(access_key_id)
+                .secret_access_key(secret_access_key)
+                .cloud_endpoint(cloud_endpoint.to_string())
+                .account(account.clone())
+                .project(project.clone())
+                .watch_config(should_watch_config)
+                .labels(labels.clone())
+                .profile(profile.clone())
+                .gw_tunnels_port(gw_tunnels_port)
+                .additional_connection_params({
+                    let mut map = HashMap::<SmolStr, SmolStr>::new();
+                    map.insert("client".into(), "cli".into());
+                    map.insert("cli_version".into(), crate_version!().into());
+                    map
                 })
+                .build()
+                .map_err(|e| anyhow::anyhow!("could not configure the client: {}", e))
+        };
+
+        let mut exogress_client = build_client(access_key_id.clone(), secret_access_key.clone())
+            .map_err(CliError::config)
+            .or_exit();
+
+        let client = async move {
+            if let Some(exofile) = exofile {
+                readiness_probe
+                    .wait_ready(&exofile, active_profile.as_deref())
+                    .await;
             }
-            _ => {
-                info!("running in standalone mode");
-                Either::Right(future::pending())
+
+            let mut access_key_id = access_key_id;
+            let mut secret_access_key = secret_access_key;
+
+            loop {
+                // reload requests of the client pass through the forwarder,
+                // so that all reloads are counted
+                let (reload_config_tx, reload_requests_rx) = mpsc::unbounded();
+                let (client_reload_tx, reload_config_rx) = mpsc::unbounded();
+                tokio::spawn(forward_config_reloads(
+                    reload_requests_rx,
+                    client_reload_tx,
+                    reload_config_path.clone(),
+                    shared_status.clone(),
+                ));
+
+                exogress_client = {
+                    let client = exogress_client.spawn(
+                        reload_config_tx.clone(),
+                        reload_config_rx,
+                        resolver.clone(),
+                    );
+                    futures::pin_mut!(client);
+
+                    shared_status.set_client_state(ClientState::Running);
+                    systemd::notify_ready();
+
+                    // on SIGHUP secrets are re-read from files. If they are rotated,
+                    // the client is reconnected with the new credentials, otherwise
+                    // only config is reloaded
+                    loop {
+                        tokio::select! {
+                            r = &mut client => {
+                                shared_status.set_client_state(ClientState::Stopped);
+                                return r;
+                            },
+                            Ok(()) = hup_rx.changed() => {
+                                match reread_credentials(
+                                    access_key_id_file.as_deref(),
+                                    secret_access_key_file.as_deref(),
+                                ) {
+                                    Ok((new_access_key_id, new_secret_access_key)) => {
+                                        let new_access_key_id = new_access_key_id
+                                            .unwrap_or_else(|| access_key_id.clone());
+                                        let new_secret_access_key = new_secret_access_key
+                                            .unwrap_or_else(|| secret_access_key.clone());
+
+                                        if new_access_key_id != access_key_id
+                                            || new_secret_access_key != secret_access_key
+                                        {
+                                            match build_client(
+                                                new_access_key_id.clone(),
+                                                new_secret_access_key.clone(),
+                                            ) {
+                                                Ok(new_client) => {
+                                                    info!("credentials rotated, reconnecting");
+                                                    shared_status
+                                                        .set_client_state(ClientState::Reconnecting);
+                                                    access_key_id = new_access_key_id;
+                                                    secret_access_key = new_secret_access_key;
+                                                    break new_client;
+                                                }
+                                                Err(e) => {
+                                                    error!(
+                                                        "credentials rotated, but the client could \
+                                                         not be reconnected: {:#}",
+                                                        e
+                                                    );
+                                                }
+                                            }
+                                        }
+                                    }
+                                    Err(e) => {
+                                        error!("could not re-read credentials: {:#}", e);
+                                    }
+                                }
+
+                                // the client is stopping if the receiver is dropped,
+                                // which is handled on the next iteration
+                                reload_config_tx.unbounded_send(()).ok();
+                            }
+                        }
+                    }
+                };
             }
         }
         .fuse();
 
-        let client = Client::builder()
-            .config_path(config_path)
-            .access_key_id(access_key_id)
-            .secret_access_key(secret_access_key)
-            .cloud_endpoint(cloud_endpoint.to_string())
-            .account(account)
-            .project(project)
-            .watch_config(should_watch_config)
-            .labels(labels)
-            .profile(profile)
-            .gw_tunnels_port(gw_tunnels_port)
-            .additional_connection_params({
-                let mut map = HashMap::<SmolStr, SmolStr>::new();
-                map.insert("client".into(), "cli".into());
-                map.insert("cli_version".into(), crate_version!().into());
-                map
-            })
-            .build()
-            .unwrap()
-            .spawn(reload_config_tx, reload_config_rx, resolver)
-            .fuse();
+        futures::pin_mut!(client);
+        futures::pin_mut!(process);
 
         tokio::select! {
-            r = client => {
-                if let Err(e) = r {
-                    error!("Client stopped with error: {}", e);
+            r = &mut client => {
+                let reason = StopReason::from_client_result(r);
+
+                if has_child {
+                    info!("Client stopped ({}), stopping the command", reason);
+                    systemd::notify_stopping();
+
+                    // the same path as on signals, so that the child is
+                    // killed if it doesn't exit within the stop timeout
+                    child_control_tx
+                        .send(ChildControl::Stop(ChildSignal::Terminate))
+                        .ok();
+                    (&mut process).await;
                 }
+
+                reason
             },
-            _ = process => {},
+            r = &mut process => r,
             r = app_stop_wait => {
                 info!("Stop request received: {}", r);
+                systemd::notify_stopping();
+
+                if has_child {
+                    // keep tunnels open while the child is shutting down,
+                    // so that in-flight requests are drained
+                    tokio::select! {
+                        r = &mut client => {
+                            // the child is already stopping, wait for it
+                            (&mut process).await;
+                            StopReason::from_client_result(r)
+                        },
+                        r = &mut process => r,
+                    }
+                } else {
+                    r
+                }
             },
         }
     });
 
-    info!("Exiting");
+    let exit_code = stop_reason.exit_code();
+
+    if exit_code == 0 {
+        info!("Exiting ({})", stop_reason);
+    } else {
+        error!("Exiting ({}), exit code {}", stop_reason, exit_code);
+    }
+
+    std::process::exit(exit_code);
 }
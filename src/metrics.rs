@@ -0,0 +1,243 @@
+//! Prometheus metrics in the text exposition format, served by the admin
+//! endpoint on `/metrics`.
+
+use crate::status::{ClientState, SharedStatus};
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Counters of events which are not reflected in the status. Child process
+/// metrics are taken from the status.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    config_reloads_succeeded: AtomicU64,
+    config_reloads_failed: AtomicU64,
+    sighups: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_config_reload(&self, is_success: bool) {
+        if is_success {
+            self.config_reloads_succeeded
+                .fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.config_reloads_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_sighup(&self) {
+        self.sighups.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    write_header(out, name, kind, help);
+    writeln!(out, "{} {}", name, value).unwrap();
+}
+
+/// Escape label value according to the exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[cfg(target_os = "linux")]
+fn resident_memory_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+    Some(pages * page_size as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(feature = "jemalloc")]
+fn write_jemalloc_stats(out: &mut String) {
+    use jemalloc_ctl::{epoch, stats};
+
+    // stats are cached by jemalloc until the epoch is advanced
+    if epoch::advance().is_err() {
+        return;
+    }
+
+    let all_stats = [
+        (
+            "exogress_jemalloc_allocated_bytes",
+            "Bytes allocated by the application",
+            stats::allocated::read(),
+        ),
+        (
+            "exogress_jemalloc_active_bytes",
+            "Bytes in active pages allocated by the application",
+            stats::active::read(),
+        ),
+        (
+            "exogress_jemalloc_metadata_bytes",
+            "Bytes dedicated to jemalloc metadata",
+            stats::metadata::read(),
+        ),
+        (
+            "exogress_jemalloc_resident_bytes",
+            "Bytes in physically resident data pages mapped by jemalloc",
+            stats::resident::read(),
+        ),
+        (
+            "exogress_jemalloc_mapped_bytes",
+            "Bytes in active extents mapped by jemalloc",
+            stats::mapped::read(),
+        ),
+        (
+            "exogress_jemalloc_retained_bytes",
+            "Bytes in virtual memory mappings retained by jemalloc",
+            stats::retained::read(),
+        ),
+    ];
+
+    for (name, help, value) in all_stats.iter() {
+        if let Ok(value) = value {
+            write_metric(out, name, "gauge", help, *value as u64);
+        }
+    }
+}
+
+#[cfg(not(feature = "jemalloc"))]
+fn write_jemalloc_stats(_out: &mut String) {}
+
+pub fn render(status: &SharedStatus) -> String {
+    let metrics = status.metrics();
+    let snapshot = status.snapshot();
+    let mut out = String::new();
+
+    write_header(
+        &mut out,
+        "exogress_info",
+        "gauge",
+        "Version of the CLI and the served config",
+    );
+    writeln!(
+        out,
+        r#"exogress_info{{version="{}",config_name="{}",config_revision="{}"}} 1"#,
+        escape(&snapshot.cli_version),
+        escape(snapshot.config_name.as_deref().unwrap_or_default()),
+        snapshot
+            .config_revision
+            .map(|r| r.to_string())
+            .unwrap_or_default(),
+    )
+    .unwrap();
+
+    write_metric(
+        &mut out,
+        "exogress_client_up",
+        "gauge",
+        "Whether the client is started",
+        (snapshot.client == ClientState::Running) as u64,
+    );
+    write_metric(
+        &mut out,
+        "exogress_sighup_total",
+        "counter",
+        "SIGHUP signals received",
+        metrics.sighups.load(Ordering::Relaxed),
+    );
+
+    write_header(
+        &mut out,
+        "exogress_config_reloads_total",
+        "counter",
+        "Config reloads by SIGHUP or config file changes, by whether the config is valid",
+    );
+    writeln!(
+        out,
+        r#"exogress_config_reloads_total{{result="success"}} {}"#,
+        metrics.config_reloads_succeeded.load(Ordering::Relaxed)
+    )
+    .unwrap();
+    writeln!(
+        out,
+        r#"exogress_config_reloads_total{{result="failure"}} {}"#,
+        metrics.config_reloads_failed.load(Ordering::Relaxed)
+    )
+    .unwrap();
+
+    if !snapshot.processes.is_empty() {
+        let process_label = |name: &Option<String>| escape(name.as_deref().unwrap_or("main"));
+
+        write_header(
+            &mut out,
+            "exogress_child_up",
+            "gauge",
+            "Whether the child process is running",
+        );
+        for process in &snapshot.processes {
+            writeln!(
+                out,
+                r#"exogress_child_up{{process="{}"}} {}"#,
+                process_label(&process.name),
+                process.is_running as u64
+            )
+            .unwrap();
+        }
+
+        write_header(
+            &mut out,
+            "exogress_child_restarts_total",
+            "counter",
+            "Restarts of the child process",
+        );
+        for process in &snapshot.processes {
+            writeln!(
+                out,
+                r#"exogress_child_restarts_total{{process="{}"}} {}"#,
+                process_label(&process.name),
+                process.restarts
+            )
+            .unwrap();
+        }
+
+        write_header(
+            &mut out,
+            "exogress_child_exits_total",
+            "counter",
+            "Exits of the child process by exit code",
+        );
+        for process in &snapshot.processes {
+            for (code, count) in &process.exit_codes {
+                writeln!(
+                    out,
+                    r#"exogress_child_exits_total{{process="{}",code="{}"}} {}"#,
+                    process_label(&process.name),
+                    code,
+                    count
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    if let Some(rss) = resident_memory_bytes() {
+        write_metric(
+            &mut out,
+            "process_resident_memory_bytes",
+            "gauge",
+            "Resident memory size in bytes",
+            rss,
+        );
+    }
+
+    write_jemalloc_stats(&mut out);
+
+    out
+}
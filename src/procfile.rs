@@ -0,0 +1,136 @@
+use crate::{
+    status::SharedStatus,
+    supervisor::{ChildControl, ChildSignal, Supervisor, SupervisorConfig},
+};
+use anyhow::{anyhow, bail, Context};
+use futures::{stream::FuturesUnordered, StreamExt};
+use std::{fs, path::Path, process::ExitStatus};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone)]
+pub struct ProcfileEntry {
+    pub name: String,
+    pub command: String,
+}
+
+impl ProcfileEntry {
+    #[cfg(unix)]
+    fn command_and_args(&self) -> Vec<String> {
+        vec!["sh".to_string(), "-c".to_string(), self.command.clone()]
+    }
+
+    #[cfg(not(unix))]
+    fn command_and_args(&self) -> Vec<String> {
+        vec!["cmd".to_string(), "/C".to_string(), self.command.clone()]
+    }
+}
+
+/// Parse Procfile with lines in the format `<name>: <command>`
+pub fn parse(content: &str) -> anyhow::Result<Vec<ProcfileEntry>> {
+    let mut entries: Vec<ProcfileEntry> = Vec::new();
+
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, command) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("line {}: expected `<name>: <command>`", idx + 1))?;
+        let name = name.trim();
+        let command = command.trim();
+
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("line {}: bad process name `{}`", idx + 1, name);
+        }
+        if command.is_empty() {
+            bail!("line {}: empty command for process `{}`", idx + 1, name);
+        }
+        if entries.iter().any(|entry| entry.name == name) {
+            bail!("line {}: duplicate process `{}`", idx + 1, name);
+        }
+
+        entries.push(ProcfileEntry {
+            name: name.to_string(),
+            command: command.to_string(),
+        });
+    }
+
+    if entries.is_empty() {
+        bail!("no processes defined");
+    }
+
+    Ok(entries)
+}
+
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Vec<ProcfileEntry>> {
+    let path = path.as_ref();
+    let content =
+        fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+
+    parse(&content).with_context(|| format!("bad procfile {}", path.display()))
+}
+
+/// Run all processes from the Procfile under supervision. Once any of them
+/// exits and is not restarted, all others are stopped as well. The status of
+/// the first exited process is returned.
+pub async fn run(
+    entries: Vec<ProcfileEntry>,
+    config: SupervisorConfig,
+    mut control_rx: mpsc::UnboundedReceiver<ChildControl>,
+    status: SharedStatus,
+) -> anyhow::Result<ExitStatus> {
+    let name_width = entries
+        .iter()
+        .map(|entry| entry.name.len())
+        .max()
+        .unwrap_or_default();
+
+    let mut control_txs = Vec::new();
+    let mut processes = FuturesUnordered::new();
+
+    for entry in entries {
+        let (tx, rx) = mpsc::unbounded_channel();
+        control_txs.push(tx);
+
+        let supervisor = Supervisor::new(entry.command_and_args(), config.clone(), rx)
+            .process_group()
+            .log_prefix(format!("{:width$}", entry.name, width = name_width))
+            .status(status.register_process(Some(entry.name.clone())));
+
+        processes.push(supervisor.run());
+    }
+
+    let mut first_result = None;
+
+    loop {
+        tokio::select! {
+            Some(result) = processes.next() => {
+                if first_result.is_none() {
+                    first_result = Some(result);
+
+                    for tx in &control_txs {
+                        tx.send(ChildControl::Stop(ChildSignal::Terminate)).ok();
+                    }
+                }
+            }
+            Some(control) = control_rx.recv() => {
+                for tx in &control_txs {
+                    tx.send(control).ok();
+                }
+            }
+            else => break,
+        }
+
+        if processes.is_empty() {
+            break;
+        }
+    }
+
+    first_result.unwrap_or_else(|| Err(anyhow!("no processes defined in the Procfile")))
+}
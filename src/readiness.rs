@@ -0,0 +1,125 @@
+use crate::exofile::Exofile;
+use anyhow::anyhow;
+use std::{fmt, str::FromStr, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+const PROBE_INTERVAL: Duration = Duration::from_millis(500);
+const PROBE_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeKind {
+    None,
+    Tcp,
+    Http,
+}
+
+impl ProbeKind {
+    pub const VARIANTS: &'static [&'static str] = &["none", "tcp", "http"];
+}
+
+impl FromStr for ProbeKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ProbeKind::None),
+            "tcp" => Ok(ProbeKind::Tcp),
+            "http" => Ok(ProbeKind::Http),
+            _ => Err(anyhow!("unknown readiness probe `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for ProbeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeKind::None => write!(f, "none"),
+            ProbeKind::Tcp => write!(f, "tcp"),
+            ProbeKind::Http => write!(f, "http"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReadinessProbe {
+    pub kind: ProbeKind,
+    pub http_path: String,
+    pub timeout: Duration,
+}
+
+impl ReadinessProbe {
+    async fn probe_once(&self, host: &str, port: u16) -> anyhow::Result<()> {
+        let mut stream = TcpStream::connect((host, port)).await?;
+
+        if self.kind == ProbeKind::Http {
+            let request = format!(
+                "GET {} HTTP/1.1\r\nHost: {}:{}\r\nUser-Agent: exogress-readiness-probe\r\nConnection: close\r\n\r\n",
+                self.http_path, host, port
+            );
+            stream.write_all(request.as_bytes()).await?;
+
+            let mut buf = [0u8; 32];
+            let len = stream.read(&mut buf).await?;
+            let status_line = std::str::from_utf8(&buf[..len])?;
+
+            // HTTP/1.1 200 OK
+            let status_code: u16 = status_line
+                .split_whitespace()
+                .nth(1)
+                .ok_or_else(|| anyhow!("bad response"))?
+                .parse()?;
+
+            if status_code >= 500 {
+                return Err(anyhow!("responded with status {}", status_code));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn wait_upstream(&self, name: &str, host: &str, port: u16) {
+        loop {
+            match tokio::time::timeout(PROBE_ATTEMPT_TIMEOUT, self.probe_once(host, port)).await {
+                Ok(Ok(())) => {
+                    info!("upstream {} at {}:{} is ready", name, host, port);
+                    return;
+                }
+                Ok(Err(e)) => {
+                    debug!("upstream {} at {}:{} is not ready: {}", name, host, port, e);
+                }
+                Err(_) => {
+                    debug!("upstream {} at {}:{} probe timed out", name, host, port);
+                }
+            }
+
+            tokio::time::sleep(PROBE_INTERVAL).await;
+        }
+    }
+
+    /// Wait until all upstreams, which are active in the profile, are ready.
+    /// Gives up with a warning when timeout is reached.
+    pub async fn wait_ready(&self, exofile: &Exofile, active_profile: Option<&str>) {
+        if self.kind == ProbeKind::None {
+            return;
+        }
+
+        let upstreams = exofile
+            .active_upstreams(active_profile)
+            .map(|(name, upstream)| self.wait_upstream(name, upstream.host(), upstream.port));
+
+        info!("waiting for upstreams to become ready");
+
+        if tokio::time::timeout(self.timeout, futures::future::join_all(upstreams))
+            .await
+            .is_err()
+        {
+            warn!(
+                "upstreams are not ready after {:?}, continue anyway",
+                self.timeout
+            );
+        }
+    }
+}
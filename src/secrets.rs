@@ -0,0 +1,36 @@
+use anyhow::{bail, Context};
+use std::{fs, path::Path};
+
+/// Read secret from the file, e.g. mounted Docker or Kubernetes secret.
+/// Trailing newlines are removed.
+pub fn read_secret_file(path: &Path) -> anyhow::Result<String> {
+    warn_if_world_readable(path);
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("could not read secret from {}", path.display()))?;
+    let secret = content.trim_end_matches(&['\r', '\n'][..]).to_string();
+
+    if secret.is_empty() {
+        bail!("secret file {} is empty", path.display());
+    }
+
+    Ok(secret)
+}
+
+#[cfg(unix)]
+fn warn_if_world_readable(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.permissions().mode() & 0o004 != 0 {
+            warn!(
+                "secret file {} is world-readable, consider `chmod o-r {}`",
+                path.display(),
+                path.display()
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn warn_if_world_readable(_path: &Path) {}
@@ -0,0 +1,120 @@
+use crate::metrics::Metrics;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::Arc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClientState {
+    /// Waiting for upstreams to become ready
+    WaitingForUpstreams,
+    /// The client is started. The client library doesn't report tunnel
+    /// connections, so tunnels may still be connecting
+    Running,
+    /// Credentials were rotated, the client reconnects
+    Reconnecting,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProcessStatus {
+    pub name: Option<String>,
+    pub pid: Option<u32>,
+    pub is_running: bool,
+    pub restarts: u32,
+    pub crashes: u32,
+    pub last_exit_code: Option<i32>,
+    /// How many times the process exited with each exit code
+    pub exit_codes: BTreeMap<i32, u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    pub cli_version: String,
+    pub client: ClientState,
+    pub config_name: Option<String>,
+    pub config_revision: Option<u64>,
+    pub profile: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    pub processes: Vec<ProcessStatus>,
+}
+
+impl Status {
+    pub fn is_ready(&self) -> bool {
+        self.client == ClientState::Running && self.processes.iter().all(|p| p.is_running)
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.client != ClientState::Stopped
+    }
+}
+
+/// Status of the running instance, shared between the client, supervisors
+/// and the admin endpoint
+#[derive(Debug, Clone)]
+pub struct SharedStatus {
+    client: Arc<Mutex<ClientState>>,
+    config: Arc<Mutex<(Option<String>, Option<u64>)>>,
+    profile: Option<String>,
+    labels: BTreeMap<String, String>,
+    processes: Arc<Mutex<Vec<Arc<Mutex<ProcessStatus>>>>>,
+    metrics: Arc<Metrics>,
+}
+
+impl SharedStatus {
+    pub fn new(profile: Option<String>, labels: BTreeMap<String, String>) -> Self {
+        SharedStatus {
+            client: Arc::new(Mutex::new(ClientState::WaitingForUpstreams)),
+            config: Default::default(),
+            profile,
+            labels,
+            processes: Default::default(),
+            metrics: Default::default(),
+        }
+    }
+
+    pub fn set_client_state(&self, state: ClientState) {
+        *self.client.lock() = state;
+    }
+
+    pub fn is_client_running(&self) -> bool {
+        *self.client.lock() == ClientState::Running
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn set_config(&self, name: Option<String>, revision: Option<u64>) {
+        *self.config.lock() = (name, revision);
+    }
+
+    /// Register new process, which is updated by its supervisor
+    pub fn register_process(&self, name: Option<String>) -> Arc<Mutex<ProcessStatus>> {
+        let process = Arc::new(Mutex::new(ProcessStatus {
+            name,
+            ..Default::default()
+        }));
+        self.processes.lock().push(process.clone());
+        process
+    }
+
+    pub fn snapshot(&self) -> Status {
+        let (config_name, config_revision) = self.config.lock().clone();
+
+        Status {
+            cli_version: clap::crate_version!().to_string(),
+            client: *self.client.lock(),
+            config_name,
+            config_revision,
+            profile: self.profile.clone(),
+            labels: self.labels.clone(),
+            processes: self
+                .processes
+                .lock()
+                .iter()
+                .map(|p| p.lock().clone())
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use crate::{
+    child_log::{ChildLogConfig, OutputStream},
+    status::ProcessStatus,
+    termination::exit_status_code,
+};
+use anyhow::anyhow;
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    process::{ExitStatus, Stdio},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    process::{Child, Command},
+    sync::mpsc,
+};
+
+/// If the child has been running for at least this long, the next crash
+/// is considered unrelated to the previous ones and backoff starts over.
+const BACKOFF_RESET_AFTER: Duration = Duration::from_secs(30);
+
+/// How long to wait for the rest of the output after the child exits. Pipes
+/// may be inherited by grandchildren, which keep them open after the child
+/// exits, so the output is not awaited indefinitely.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    Never,
+    OnFailure,
+    Always,
+}
+
+impl RestartPolicy {
+    pub const VARIANTS: &'static [&'static str] = &["never", "on-failure", "always"];
+
+    fn should_restart(&self, status: &ExitStatus) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => !status.success(),
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(RestartPolicy::Never),
+            "on-failure" => Ok(RestartPolicy::OnFailure),
+            "always" => Ok(RestartPolicy::Always),
+            _ => Err(anyhow!("unknown restart policy `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartPolicy::Never => write!(f, "never"),
+            RestartPolicy::OnFailure => write!(f, "on-failure"),
+            RestartPolicy::Always => write!(f, "always"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildSignal {
+    Terminate,
+    Interrupt,
+    Hangup,
+}
+
+impl ChildSignal {
+    /// Send the signal to the child, or to its whole process group
+    #[cfg(unix)]
+    fn send_to(&self, child: &mut Child, process_group: bool) {
+        let signal = match self {
+            ChildSignal::Terminate => libc::SIGTERM,
+            ChildSignal::Interrupt => libc::SIGINT,
+            ChildSignal::Hangup => libc::SIGHUP,
+        };
+
+        if let Some(pid) = child.id() {
+            let pid = pid as libc::pid_t;
+            unsafe {
+                libc::kill(if process_group { -pid } else { pid }, signal);
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn send_to(&self, child: &mut Child, _process_group: bool) {
+        match self {
+            ChildSignal::Terminate | ChildSignal::Interrupt => {
+                child.start_kill().ok();
+            }
+            ChildSignal::Hangup => {}
+        }
+    }
+}
+
+impl fmt::Display for ChildSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChildSignal::Terminate => write!(f, "SIGTERM"),
+            ChildSignal::Interrupt => write!(f, "SIGINT"),
+            ChildSignal::Hangup => write!(f, "SIGHUP"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ChildControl {
+    /// Forward the signal to the running child.
+    Signal(ChildSignal),
+    /// Send the signal to the child, wait for it to exit and don't restart it.
+    Stop(ChildSignal),
+}
+
+#[derive(Debug, Clone)]
+pub struct SupervisorConfig {
+    pub policy: RestartPolicy,
+    pub max_restarts: Option<u32>,
+    pub backoff_initial: Duration,
+    pub backoff_max: Duration,
+    pub stop_timeout: Duration,
+    pub log: ChildLogConfig,
+}
+
+/// Runs the child command and restarts it according to the restart policy.
+pub struct Supervisor {
+    command: Vec<String>,
+    config: SupervisorConfig,
+    control_rx: mpsc::UnboundedReceiver<ChildControl>,
+    log_prefix: Option<String>,
+    status: Arc<Mutex<ProcessStatus>>,
+    restarts: u32,
+    crashes: u32,
+    stopping: bool,
+    process_group: bool,
+}
+
+impl Supervisor {
+    pub fn new(
+        command: Vec<String>,
+        config: SupervisorConfig,
+        control_rx: mpsc::UnboundedReceiver<ChildControl>,
+    ) -> Self {
+        assert!(!command.is_empty(), "empty command");
+
+        Supervisor {
+            command,
+            config,
+            control_rx,
+            log_prefix: None,
+            status: Default::default(),
+            restarts: 0,
+            crashes: 0,
+            stopping: false,
+            process_group: false,
+        }
+    }
+
+    /// Prefix each line of the child output, so that output of several
+    /// processes may be told apart.
+    pub fn log_prefix(mut self, log_prefix: impl Into<String>) -> Self {
+        self.log_prefix = Some(log_prefix.into());
+        self
+    }
+
+    /// Run the child in its own process group and send signals to the whole
+    /// group, so that they reach the processes started by a shell as well.
+    pub fn process_group(mut self) -> Self {
+        self.process_group = true;
+        self
+    }
+
+    /// Report process status to the shared status
+    pub fn status(mut self, status: Arc<Mutex<ProcessStatus>>) -> Self {
+        self.status = status;
+        self
+    }
+
+    fn process_name(&self) -> String {
+        match &self.log_prefix {
+            Some(prefix) => format!("process {}", prefix.trim()),
+            None => "child process".to_string(),
+        }
+    }
+
+    async fn stop_child(
+        &mut self,
+        child: &mut Child,
+        signal: ChildSignal,
+    ) -> anyhow::Result<ExitStatus> {
+        self.stopping = true;
+
+        info!(
+            "sending {} to {}, waiting up to {:?}",
+            signal,
+            self.process_name(),
+            self.config.stop_timeout
+        );
+        signal.send_to(child, self.process_group);
+
+        match tokio::time::timeout(self.config.stop_timeout, child.wait()).await {
+            Ok(status) => Ok(status?),
+            Err(_) => {
+                warn!(
+                    "{} didn't exit in {:?}, killing it",
+                    self.process_name(),
+                    self.config.stop_timeout
+                );
+                #[cfg(unix)]
+                if self.process_group {
+                    if let Some(pid) = child.id() {
+                        unsafe {
+                            libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+                        }
+                    }
+                }
+                child.kill().await?;
+                Ok(child.wait().await?)
+            }
+        }
+    }
+
+    async fn run_once(&mut self) -> anyhow::Result<ExitStatus> {
+        let mut command = Command::new(&self.command[0]);
+
+        command
+            .args(&self.command[1..])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        #[cfg(unix)]
+        if self.process_group {
+            unsafe {
+                command.pre_exec(|| {
+                    if libc::setpgid(0, 0) == 0 {
+                        Ok(())
+                    } else {
+                        Err(std::io::Error::last_os_error())
+                    }
+                });
+            }
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| anyhow!("failed to spawn command `{}`: {}", self.command[0], e))?;
+
+        info!("{} started with pid {:?}", self.process_name(), child.id());
+
+        {
+            let mut status = self.status.lock();
+            status.pid = child.id();
+            status.is_running = true;
+        }
+
+        let stderr = child.stderr.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let stdout_forward = tokio::spawn(self.config.log.clone().forward(
+            stdout,
+            tokio::io::stdout(),
+            OutputStream::Stdout,
+            child.id(),
+            self.log_prefix.clone(),
+        ));
+        let stderr_forward = tokio::spawn(self.config.log.clone().forward(
+            stderr,
+            tokio::io::stderr(),
+            OutputStream::Stderr,
+            child.id(),
+            self.log_prefix.clone(),
+        ));
+
+        let status = loop {
+            let control = tokio::select! {
+                status = child.wait() => break status?,
+                control = self.control_rx.recv() => control,
+            };
+
+            match control {
+                Some(ChildControl::Signal(signal)) => {
+                    info!("forwarding {} to {}", signal, self.process_name());
+                    signal.send_to(&mut child, self.process_group);
+                }
+                Some(ChildControl::Stop(signal)) => {
+                    break self.stop_child(&mut child, signal).await?;
+                }
+                None => break child.wait().await?,
+            }
+        };
+
+        for mut forward in vec![stdout_forward, stderr_forward] {
+            if tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, &mut forward)
+                .await
+                .is_err()
+            {
+                debug!(
+                    "output of {} is still open after exit, probably inherited by its children",
+                    self.process_name()
+                );
+                forward.abort();
+            }
+        }
+
+        {
+            let mut process_status = self.status.lock();
+            process_status.pid = None;
+            process_status.is_running = false;
+            let exit_code = exit_status_code(&status);
+            process_status.last_exit_code = Some(exit_code);
+            *process_status.exit_codes.entry(exit_code).or_default() += 1;
+        }
+
+        Ok(status)
+    }
+
+    /// Supervise the child until the policy says it should not be
+    /// restarted anymore. Returns the last exit status.
+    pub async fn run(mut self) -> anyhow::Result<ExitStatus> {
+        let mut backoff = self.config.backoff_initial;
+
+        loop {
+            let started_at = Instant::now();
+            let status = self.run_once().await?;
+
+            if self.stopping {
+                info!("{} stopped with {}", self.process_name(), status);
+                return Ok(status);
+            }
+
+            if !status.success() {
+                self.crashes += 1;
+                self.status.lock().crashes = self.crashes;
+            }
+
+            if !self.config.policy.should_restart(&status) {
+                info!("{} exited with {}", self.process_name(), status);
+                return Ok(status);
+            }
+
+            if let Some(max_restarts) = self.config.max_restarts {
+                if self.restarts >= max_restarts {
+                    warn!(
+                        "{} exited with {}, giving up after {} restarts",
+                        self.process_name(),
+                        status,
+                        self.restarts
+                    );
+                    return Ok(status);
+                }
+            }
+
+            if started_at.elapsed() >= BACKOFF_RESET_AFTER {
+                backoff = self.config.backoff_initial;
+            }
+
+            warn!(
+                "{} exited with {} (crashes: {}, restarts: {}), restarting in {:?}",
+                self.process_name(),
+                status,
+                self.crashes,
+                self.restarts,
+                backoff
+            );
+
+            let sleep = tokio::time::sleep(backoff);
+            tokio::pin!(sleep);
+
+            loop {
+                let control = tokio::select! {
+                    _ = &mut sleep => break,
+                    control = self.control_rx.recv() => control,
+                };
+
+                match control {
+                    Some(ChildControl::Stop(_)) => return Ok(status),
+                    Some(ChildControl::Signal(_)) => {}
+                    None => {
+                        (&mut sleep).await;
+                        break;
+                    }
+                }
+            }
+
+            backoff = std::cmp::min(backoff * 2, self.config.backoff_max);
+            self.restarts += 1;
+            self.status.lock().restarts = self.restarts;
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Notifications for the systemd service manager, so that exogress may run
+//! as a `Type=notify` service. Notifications are sent only if `NOTIFY_SOCKET`
+//! is set, so they are no-op outside of systemd.
+
+use crate::status::SharedStatus;
+use std::{env, time::Duration};
+
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+const WATCHDOG_USEC: &str = "WATCHDOG_USEC";
+const WATCHDOG_PID: &str = "WATCHDOG_PID";
+
+#[cfg(unix)]
+fn send(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let path = match env::var_os(NOTIFY_SOCKET) {
+        Some(path) => path,
+        None => return,
+    };
+
+    let result = UnixDatagram::unbound().and_then(|socket| {
+        let path = path.to_string_lossy();
+
+        // addresses starting with `@` are in the abstract namespace
+        #[cfg(target_os = "linux")]
+        if let Some(name) = path.strip_prefix('@') {
+            return send_to_abstract(&socket, name.as_bytes(), state.as_bytes());
+        }
+
+        socket.send_to(state.as_bytes(), path.as_ref())
+    });
+
+    if let Err(e) = result {
+        warn!("could not notify systemd: {}", e);
+    }
+}
+
+/// Send to the socket in the abstract namespace. `sendto` is called
+/// directly, since std supports abstract addresses only since Rust 1.70
+#[cfg(target_os = "linux")]
+fn send_to_abstract(
+    socket: &std::os::unix::net::UnixDatagram,
+    name: &[u8],
+    message: &[u8],
+) -> std::io::Result<()> {
+    use std::{io, mem, os::unix::io::AsRawFd};
+
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+    // the name follows the leading zero byte of `sun_path`
+    if name.len() >= addr.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "socket name is too long",
+        ));
+    }
+    for (dst, src) in addr.sun_path[1..].iter_mut().zip(name) {
+        *dst = *src as libc::c_char;
+    }
+    let addr_len = mem::size_of::<libc::sa_family_t>() + 1 + name.len();
+
+    let sent = unsafe {
+        libc::sendto(
+            socket.as_raw_fd(),
+            message.as_ptr() as *const libc::c_void,
+            message.len(),
+            0,
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            addr_len as libc::socklen_t,
+        )
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_state: &str) {}
+
+/// The client is started, or the config is reloaded
+pub fn notify_ready() {
+    send("READY=1");
+}
+
+/// Configuration is being reloaded. Should be followed by `notify_ready`.
+pub fn notify_reloading() {
+    send("RELOADING=1");
+}
+
+pub fn notify_stopping() {
+    send("STOPPING=1");
+}
+
+/// Watchdog interval requested by `WatchdogSec=`, if the watchdog is
+/// enabled for this process
+fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = env::var(WATCHDOG_USEC).ok()?.parse().ok()?;
+
+    if let Ok(pid) = env::var(WATCHDOG_PID) {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+
+    Some(Duration::from_micros(usec))
+}
+
+/// Ping the watchdog twice per interval, as recommended by systemd. Pings
+/// are sent while the process loop is alive, regardless of the connection to
+/// the cloud, since the client reconnects by itself. They stop once the
+/// client is stopped, so that systemd restarts a hung service.
+pub async fn run_watchdog(status: SharedStatus) {
+    let interval = match watchdog_interval() {
+        Some(interval) => interval / 2,
+        None => return,
+    };
+
+    info!("systemd watchdog is enabled, ping every {:?}", interval);
+
+    while status.snapshot().is_healthy() {
+        send("WATCHDOG=1");
+        tokio::time::sleep(interval).await;
+    }
+}
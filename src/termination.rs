@@ -1,9 +1,61 @@
+use crate::{
+    error::{CHILD_SPAWN_FAILED_EXIT_CODE, NETWORK_ERROR_EXIT_CODE},
+    supervisor::{ChildControl, ChildSignal},
+};
 use exogress_common::common_utils::termination::StopSignal;
-use std::{fmt, panic::UnwindSafe};
+use std::{fmt, panic::UnwindSafe, process::ExitStatus};
+use stop_handle::StopHandle;
+use tokio::sync::mpsc;
 
 #[derive(Debug, Clone)]
 pub enum StopReason {
     SignalReceived,
+    ChildExited(ExitStatus),
+    ChildSpawnFailed(String),
+    ClientStopped,
+    ClientFailed(String),
+}
+
+impl StopReason {
+    pub fn from_child_result(result: anyhow::Result<ExitStatus>) -> Self {
+        match result {
+            Ok(status) => StopReason::ChildExited(status),
+            Err(e) => StopReason::ChildSpawnFailed(e.to_string()),
+        }
+    }
+
+    pub fn from_client_result<T, E: fmt::Display>(result: Result<T, E>) -> Self {
+        match result {
+            Ok(_) => StopReason::ClientStopped,
+            Err(e) => StopReason::ClientFailed(e.to_string()),
+        }
+    }
+
+    /// The exit code exogress should exit with
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            StopReason::SignalReceived | StopReason::ClientStopped => 0,
+            StopReason::ChildExited(status) => exit_status_code(status),
+            StopReason::ChildSpawnFailed(_) => CHILD_SPAWN_FAILED_EXIT_CODE,
+            StopReason::ClientFailed(_) => NETWORK_ERROR_EXIT_CODE,
+        }
+    }
+}
+
+#[cfg(unix)]
+pub fn exit_status_code(status: &ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn exit_status_code(status: &ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
 }
 
 impl UnwindSafe for StopReason {}
@@ -12,6 +64,10 @@ impl fmt::Display for StopReason {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             StopReason::SignalReceived => write!(f, "signal received"),
+            StopReason::ChildExited(status) => write!(f, "child process exited with {}", status),
+            StopReason::ChildSpawnFailed(e) => write!(f, "child process failed: {}", e),
+            StopReason::ClientStopped => write!(f, "client stopped"),
+            StopReason::ClientFailed(e) => write!(f, "client stopped with error: {}", e),
         }
     }
 }
@@ -21,3 +77,44 @@ impl StopSignal for StopReason {
         StopReason::SignalReceived
     }
 }
+
+#[cfg(unix)]
+async fn next_stop_signal() -> Option<ChildSignal> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate()).ok()?;
+    let mut sigint = signal(SignalKind::interrupt()).ok()?;
+
+    tokio::select! {
+        Some(_) = sigterm.recv() => Some(ChildSignal::Terminate),
+        Some(_) = sigint.recv() => Some(ChildSignal::Interrupt),
+        else => None,
+    }
+}
+
+#[cfg(not(unix))]
+async fn next_stop_signal() -> Option<ChildSignal> {
+    tokio::signal::ctrl_c().await.ok()?;
+
+    Some(ChildSignal::Interrupt)
+}
+
+/// Stop the app on termination signals and ask the child to stop with the
+/// same signal, so that it may shut down gracefully.
+pub async fn stop_signal_listener(
+    stop_handle: StopHandle<StopReason>,
+    child_control_tx: mpsc::UnboundedSender<ChildControl>,
+) {
+    let mut is_stopping = false;
+
+    while let Some(signal) = next_stop_signal().await {
+        info!("{} received", signal);
+
+        child_control_tx.send(ChildControl::Stop(signal)).ok();
+
+        if !is_stopping {
+            is_stopping = true;
+            stop_handle.stop(StopReason::SignalReceived);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! End-to-end tests of the CLI against the mock cloud from `support`, which
+//! runs offline
+
+mod support;
+
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Child, Output, Stdio},
+    time::Duration,
+};
+use support::{MockCloud, MockOptions};
+
+fn invalidate(mock: &MockCloud, invalidation: &str) -> Output {
+    mock.exogress()
+        .arg("invalidate")
+        .arg("--")
+        .arg(invalidation)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn invalidate_sends_request() {
+    let mock = MockCloud::start(Default::default());
+
+    let output = invalidate(&mock, "assets/static/default");
+    assert!(output.status.success(), "{:?}", output);
+
+    let requests = mock.requests();
+    assert_eq!(requests.len(), 1, "{:?}", requests);
+    assert!(requests[0]["body"].as_str().unwrap().contains("assets"));
+}
+
+#[test]
+fn invalidate_fails_on_server_error() {
+    let mock = MockCloud::start(MockOptions {
+        status: 500,
+        ..Default::default()
+    });
+
+    let output = invalidate(&mock, "assets/static/default");
+    assert_eq!(output.status.code(), Some(69), "{:?}", output);
+}
+
+#[test]
+fn invalidate_rejects_bad_format_offline() {
+    let mock = MockCloud::start(Default::default());
+
+    let output = invalidate(&mock, "assets");
+    assert_eq!(output.status.code(), Some(64), "{:?}", output);
+    assert!(mock.requests().is_empty());
+}
+
+/// Running `exogress spawn` with the config, which is killed on drop
+struct Client {
+    process: Child,
+    config_dir: PathBuf,
+}
+
+impl Client {
+    fn spawn(mock: &MockCloud, name: &str, config: &str, args: &[&str]) -> Self {
+        let config_dir =
+            std::env::temp_dir().join(format!("exogress-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(config_dir.join("Exofile.yml"), config).unwrap();
+
+        let process = mock
+            .exogress()
+            .arg("spawn")
+            .arg("--config")
+            .arg(config_dir.join("Exofile.yml"))
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        Client {
+            process,
+            config_dir,
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.config_dir);
+    }
+}
+
+const CONFIG: &str = "---\nversion: 1.0.0\nrevision: 1\nname: test\nmount-points: {}\n";
+
+/// Whether the client sent the text in any request or websocket message
+fn is_sent(records: &[serde_json::Value], text: &str) -> bool {
+    records
+        .iter()
+        .filter(|r| r["kind"] == "request" || r["kind"] == "websocket-message")
+        .any(|r| r.to_string().contains(text))
+}
+
+#[test]
+fn spawn_reconnects_to_cloud() {
+    let mock = MockCloud::start(MockOptions {
+        drop_tunnels_after: Some(Duration::from_secs(1)),
+        ..Default::default()
+    });
+    let _client = Client::spawn(&mock, "spawn", CONFIG, &[]);
+
+    // the mock closes tunnels after a second, so the client has to connect
+    // a tunnel again after the first one is closed
+    let reconnected = mock.wait_for(Duration::from_secs(30), |records| {
+        let mut expected = ["tunnel-connected", "tunnel-closed", "tunnel-connected"]
+            .iter()
+            .peekable();
+        for record in records {
+            if expected
+                .peek()
+                .map_or(false, |kind| record["kind"] == **kind)
+            {
+                expected.next();
+            }
+        }
+        expected.peek().is_none()
+    });
+
+    assert!(reconnected, "{:?}", mock.records());
+}
+
+#[test]
+fn spawn_sends_labels() {
+    let mock = MockCloud::start(Default::default());
+    let _client = Client::spawn(
+        &mock,
+        "spawn-labels",
+        CONFIG,
+        &["--label", "e2e-label=e2e-value"],
+    );
+
+    let is_labels_sent = mock.wait_for(Duration::from_secs(30), |records| {
+        is_sent(records, "e2e-label") && is_sent(records, "e2e-value")
+    });
+
+    assert!(is_labels_sent, "{:?}", mock.records());
+}
+
+#[test]
+fn spawn_uploads_config() {
+    let mock = MockCloud::start(Default::default());
+    let _client = Client::spawn(
+        &mock,
+        "spawn-config",
+        "---\nversion: 1.0.0\nrevision: 42\nname: e2e-config\nmount-points: {}\n",
+        &[],
+    );
+
+    let is_uploaded = mock.wait_for(Duration::from_secs(30), |records| {
+        is_sent(records, "e2e-config")
+    });
+
+    assert!(is_uploaded, "{:?}", mock.records());
+}
@@ -0,0 +1,267 @@
+//! Local stand-in for the cloud, so that the CLI is tested offline.
+//!
+//! Serves the API and cloud endpoints over plain HTTP and accepts TCP
+//! connections on the tunnels port, in the test process. Websocket upgrades
+//! are accepted, so that the client may send its labels and config over the
+//! channel. Every request, websocket message and tunnel connection is
+//! recorded, so the test may check what the client sent. Tunnels are not
+//! established, since the mock doesn't speak the tunnel protocol, but
+//! connections are recorded, which is enough to check reconnects.
+
+use futures::StreamExt;
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    upgrade::OnUpgrade,
+    Body, Request, Response, StatusCode,
+};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    net::SocketAddr,
+    process::Command,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::AsyncReadExt,
+    net::{TcpListener, TcpStream},
+    runtime::Runtime,
+};
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+    WebSocketStream,
+};
+
+const EXOGRESS: &str = env!("CARGO_BIN_EXE_exogress");
+
+const ACCESS_KEY_ID: &str = "01F8MECHZX3TBDSZ7XRADM79XV";
+
+#[derive(Debug, Clone)]
+pub struct MockOptions {
+    /// Status of responses to the API and cloud endpoint
+    pub status: u16,
+    /// Close tunnel connections after the duration, to trigger reconnects
+    pub drop_tunnels_after: Option<Duration>,
+}
+
+impl Default for MockOptions {
+    fn default() -> Self {
+        MockOptions {
+            status: 200,
+            drop_tunnels_after: None,
+        }
+    }
+}
+
+struct State {
+    options: MockOptions,
+    records: Mutex<Vec<Value>>,
+}
+
+impl State {
+    fn record(&self, record: Value) {
+        self.records.lock().push(record);
+    }
+
+    async fn handle(self: Arc<Self>, mut req: Request<Body>) -> Response<Body> {
+        let status = self.options.status;
+        let accept_key = req
+            .headers()
+            .get(header::SEC_WEBSOCKET_KEY)
+            .filter(|_| status == 200)
+            .map(|key| derive_accept_key(key.as_bytes()));
+        let on_upgrade = accept_key.as_ref().map(|_| hyper::upgrade::on(&mut req));
+
+        let (parts, body) = req.into_parts();
+        let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+
+        self.record(json!({
+            "kind": "request",
+            "method": parts.method.as_str(),
+            "path": parts.uri.path(),
+            "query": parts.uri.query(),
+            "headers": parts
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_string(),
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })
+                .collect::<BTreeMap<_, _>>(),
+            "body": String::from_utf8_lossy(&body),
+            "status": if on_upgrade.is_some() { 101 } else { status },
+        }));
+
+        if let (Some(accept_key), Some(on_upgrade)) = (accept_key, on_upgrade) {
+            tokio::spawn(self.handle_websocket(on_upgrade, parts.uri.path().to_string()));
+
+            return Response::builder()
+                .status(StatusCode::SWITCHING_PROTOCOLS)
+                .header(header::CONNECTION, "upgrade")
+                .header(header::UPGRADE, "websocket")
+                .header(header::SEC_WEBSOCKET_ACCEPT, accept_key)
+                .body(Body::empty())
+                .unwrap();
+        }
+
+        Response::builder()
+            .status(StatusCode::from_u16(status).unwrap_or(StatusCode::OK))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from("{}"))
+            .unwrap()
+    }
+
+    async fn handle_websocket(self: Arc<Self>, on_upgrade: OnUpgrade, path: String) {
+        let upgraded = match on_upgrade.await {
+            Ok(upgraded) => upgraded,
+            Err(_) => return,
+        };
+
+        let mut websocket = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+        while let Some(Ok(message)) = websocket.next().await {
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Binary(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                _ => continue,
+            };
+            self.record(json!({
+                "kind": "websocket-message",
+                "path": path,
+                "text": text,
+            }));
+        }
+    }
+
+    async fn handle_tunnel(self: Arc<Self>, mut stream: TcpStream, peer: SocketAddr) {
+        self.record(json!({ "kind": "tunnel-connected", "peer": peer.to_string() }));
+
+        let mut bytes_received = 0;
+        let mut buf = [0u8; 4096];
+        let read_all = async {
+            while let Ok(n) = stream.read(&mut buf).await {
+                if n == 0 {
+                    break;
+                }
+                bytes_received += n as u64;
+            }
+        };
+
+        match self.options.drop_tunnels_after {
+            Some(duration) => {
+                let _ = tokio::time::timeout(duration, read_all).await;
+            }
+            None => read_all.await,
+        }
+
+        self.record(json!({
+            "kind": "tunnel-closed",
+            "peer": peer.to_string(),
+            "bytes_received": bytes_received,
+        }));
+    }
+}
+
+async fn accept_tunnels(listener: TcpListener, state: Arc<State>) {
+    while let Ok((stream, peer)) = listener.accept().await {
+        tokio::spawn(state.clone().handle_tunnel(stream, peer));
+    }
+}
+
+/// Running mock, which is stopped on drop
+pub struct MockCloud {
+    _runtime: Runtime,
+    state: Arc<State>,
+    env: Vec<(String, String)>,
+}
+
+impl MockCloud {
+    pub fn start(options: MockOptions) -> Self {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let state = Arc::new(State {
+            options,
+            records: Default::default(),
+        });
+
+        let (api_addr, tunnels_addr) = runtime.block_on(async {
+            let make_svc = {
+                let state = state.clone();
+                make_service_fn(move |_conn| {
+                    let state = state.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |req| {
+                            let state = state.clone();
+                            async move { Ok::<_, Infallible>(state.handle(req).await) }
+                        }))
+                    }
+                })
+            };
+            let api = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+            let api_addr = api.local_addr();
+            tokio::spawn(api);
+
+            let tunnels_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let tunnels_addr = tunnels_listener.local_addr().unwrap();
+            tokio::spawn(accept_tunnels(tunnels_listener, state.clone()));
+
+            (api_addr, tunnels_addr)
+        });
+
+        let env = vec![
+            ("EXG_CLOUD_ENDPOINT", format!("http://{}", api_addr)),
+            ("EXG_CLOUD_API_OVERRIDE", format!("http://{}", api_addr)),
+            ("EXG_GW_TUNNELS_PORT", tunnels_addr.port().to_string()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        MockCloud {
+            _runtime: runtime,
+            state,
+            env,
+        }
+    }
+
+    /// Command of the client, which is pointed to the mock
+    pub fn exogress(&self) -> Command {
+        let mut cmd = Command::new(EXOGRESS);
+        cmd.envs(self.env.iter().map(|(k, v)| (k, v)))
+            .env("EXG_ACCESS_KEY_ID", ACCESS_KEY_ID)
+            .env("EXG_SECRET_ACCESS_KEY", "secret")
+            .env("EXG_ACCOUNT", "test-account")
+            .env("EXG_PROJECT", "test-project");
+        cmd
+    }
+
+    pub fn records(&self) -> Vec<Value> {
+        self.state.records.lock().clone()
+    }
+
+    pub fn requests(&self) -> Vec<Value> {
+        self.records()
+            .into_iter()
+            .filter(|r| r["kind"] == "request")
+            .collect()
+    }
+
+    /// Wait until the predicate is true for the records
+    pub fn wait_for(&self, timeout: Duration, f: impl Fn(&[Value]) -> bool) -> bool {
+        let started_at = Instant::now();
+        while started_at.elapsed() < timeout {
+            if f(&self.records()) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        false
+    }
+}